@@ -0,0 +1,64 @@
+use serde::{Deserialize, Serialize};
+
+/// Plain RGB color, kept free of any rendering library so the simulation
+/// can run headless. Front ends convert it to whatever their console uses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Color {
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Color { r, g, b }
+    }
+}
+
+pub const BLACK: Color = Color::new(0, 0, 0);
+pub const WHITE: Color = Color::new(255, 255, 255);
+pub const RED: Color = Color::new(255, 0, 0);
+pub const GREEN: Color = Color::new(0, 255, 0);
+pub const ORANGE: Color = Color::new(255, 127, 0);
+pub const YELLOW: Color = Color::new(255, 255, 0);
+pub const VIOLET: Color = Color::new(127, 0, 255);
+pub const SKY: Color = Color::new(0, 191, 255);
+pub const SILVER: Color = Color::new(203, 203, 203);
+
+pub const LIGHT_GREEN: Color = Color::new(63, 255, 63);
+pub const LIGHT_BLUE: Color = Color::new(63, 63, 255);
+pub const LIGHT_VIOLET: Color = Color::new(159, 63, 255);
+pub const LIGHT_YELLOW: Color = Color::new(255, 255, 63);
+
+pub const DARK_RED: Color = Color::new(191, 0, 0);
+pub const DARK_ORANGE: Color = Color::new(191, 95, 0);
+pub const DARKER_ORANGE: Color = Color::new(127, 63, 0);
+pub const DARKER_GREEN: Color = Color::new(0, 127, 0);
+pub const DESATURATED_GREEN: Color = Color::new(63, 127, 63);
+
+/// Looks up one of the colors above by its lowercase name, like
+/// "desaturated_green", for data files.
+pub fn by_name(name: &str) -> Option<Color> {
+    let color = match name {
+        "black" => BLACK,
+        "white" => WHITE,
+        "red" => RED,
+        "green" => GREEN,
+        "orange" => ORANGE,
+        "yellow" => YELLOW,
+        "violet" => VIOLET,
+        "sky" => SKY,
+        "silver" => SILVER,
+        "light_green" => LIGHT_GREEN,
+        "light_blue" => LIGHT_BLUE,
+        "light_violet" => LIGHT_VIOLET,
+        "light_yellow" => LIGHT_YELLOW,
+        "dark_red" => DARK_RED,
+        "dark_orange" => DARK_ORANGE,
+        "darker_orange" => DARKER_ORANGE,
+        "darker_green" => DARKER_GREEN,
+        "desaturated_green" => DESATURATED_GREEN,
+        _ => return None,
+    };
+    Some(color)
+}
@@ -0,0 +1,94 @@
+use super::gamemap::Map;
+
+pub const TORCH_RADIUS: i32 = 10;
+
+/// Field of view computed by casting straight rays from the viewer to every
+/// cell on the edge of its light radius. Walls that stop a ray are lit too.
+#[derive(Default)]
+pub struct Fov {
+    width: i32,
+    height: i32,
+    visible: Vec<bool>,
+}
+
+impl Fov {
+    pub fn new(width: i32, height: i32) -> Self {
+        Fov {
+            width,
+            height,
+            visible: vec![false; (width * height) as usize],
+        }
+    }
+
+    pub fn is_in_fov(&self, x: i32, y: i32) -> bool {
+        self.in_bounds(x, y) && self.visible[self.index(x, y)]
+    }
+
+    pub fn compute(&mut self, map: &Map, x: i32, y: i32, radius: i32) {
+        let width = map.len() as i32;
+        let height = map.first().map_or(0, |column| column.len() as i32);
+        if (width, height) != (self.width, self.height) {
+            *self = Fov::new(width, height);
+        }
+
+        for cell in self.visible.iter_mut() {
+            *cell = false;
+        }
+
+        if !self.in_bounds(x, y) {
+            return;
+        }
+
+        let index = self.index(x, y);
+        self.visible[index] = true;
+
+        for d in -radius..=radius {
+            self.cast_ray(map, x, y, x + d, y - radius, radius);
+            self.cast_ray(map, x, y, x + d, y + radius, radius);
+            self.cast_ray(map, x, y, x - radius, y + d, radius);
+            self.cast_ray(map, x, y, x + radius, y + d, radius);
+        }
+    }
+
+    /// Walks a Bresenham line from the origin towards the target until it
+    /// leaves the map, the light radius or hits something opaque.
+    fn cast_ray(&mut self, map: &Map, x0: i32, y0: i32, x1: i32, y1: i32, radius: i32) {
+        let dx = (x1 - x0).abs();
+        let dy = -(y1 - y0).abs();
+        let sx = if x0 < x1 { 1 } else { -1 };
+        let sy = if y0 < y1 { 1 } else { -1 };
+        let mut err = dx + dy;
+        let (mut x, mut y) = (x0, y0);
+
+        while (x, y) != (x1, y1) {
+            let e2 = 2 * err;
+            if e2 >= dy {
+                err += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                err += dx;
+                y += sy;
+            }
+
+            if !self.in_bounds(x, y) || (x - x0).pow(2) + (y - y0).pow(2) > radius.pow(2) {
+                return;
+            }
+
+            let index = self.index(x, y);
+            self.visible[index] = true;
+
+            if map[x as usize][y as usize].block_sight {
+                return;
+            }
+        }
+    }
+
+    fn in_bounds(&self, x: i32, y: i32) -> bool {
+        x >= 0 && y >= 0 && x < self.width && y < self.height
+    }
+
+    fn index(&self, x: i32, y: i32) -> usize {
+        (y * self.width + x) as usize
+    }
+}
@@ -0,0 +1,1002 @@
+use std::cmp;
+use std::collections::BTreeMap;
+
+use super::colors::*;
+use super::fov::{Fov, TORCH_RADIUS};
+use super::gamemap::*;
+use super::messages::Messages;
+use super::object::*;
+use super::templates::Templates;
+
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg32;
+use serde::{Deserialize, Serialize};
+
+pub const PLAYER: usize = 0;
+const HEAL_AMOUNT: i32 = 4;
+const LIGHTNING_DAMAGE: i32 = 40;
+const LIGHTNING_RANGE: i32 = 5;
+const CONFUSE_RANGE: i32 = 8;
+const CONFUSE_NUM_TURNS: i32 = 10;
+const FIREBALL_RANGE: i32 = 8;
+const FIREBALL_RADIUS: i32 = 3;
+const FIREBALL_DAMAGE: i32 = 25;
+
+/// Energy an actor needs before it may act. Actors gain their speed in
+/// energy every tick of game time and spend it on actions, so one game
+/// turn is `TURN_ENERGY / NORMAL_SPEED` ticks.
+pub const TURN_ENERGY: i32 = 100;
+pub const NORMAL_SPEED: i32 = 10;
+const TICKS_PER_TURN: u64 = (TURN_ENERGY / NORMAL_SPEED) as u64;
+
+/// What a plain move or attack costs; other actions are priced relative
+/// to it in `energy_cost`.
+const ACTION_COST: i32 = 100;
+
+const LEVEL_UP_BASE: i32 = 200;
+const LEVEL_UP_FACTOR: i32 = 150;
+pub const LEVEL_UP_HP: i32 = 20;
+
+/// Everything a front end can ask the player character to do.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Command {
+    /// Step by the given offset, attacking whatever fighter stands there.
+    Move { dx: i32, dy: i32 },
+    /// Stand still for a turn.
+    Wait,
+    PickUp,
+    /// Use the item at this index of `Game::inventory`. Items with an
+    /// `item_target_range` need the tile picked by the player; `None`
+    /// cancels them.
+    UseItem(usize, Option<(i32, i32)>),
+    /// Take the stairs the player is standing on down to the next level.
+    Descend,
+    /// Put on the equipment at this inventory index, swapping out whatever
+    /// occupies the same slot.
+    Equip(usize),
+    Unequip(usize),
+    /// Put the item at this inventory index on the floor under the player,
+    /// taking it off first if it is equipped.
+    Drop(usize),
+    /// Spend a pending level-up, see `Game::can_level_up`.
+    LevelUp(LevelUpChoice),
+    /// Shut the open door at this offset from the player. Doors are opened
+    /// by moving into them.
+    CloseDoor { dx: i32, dy: i32 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LevelUpChoice {
+    MaxHp,
+    Power,
+    Defense,
+}
+
+/// What happened while a command was carried out, in order.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    PlayerMoved { x: i32, y: i32 },
+    Attacked {
+        attacker: String,
+        target: String,
+        damage: i32,
+    },
+    Died { name: String },
+    PickedUp { name: String },
+    Dropped { name: String },
+    InventoryFull { name: String },
+    ItemUsed { name: String },
+    ItemCancelled { name: String },
+    Descended { level: u32 },
+    DoorOpened { x: i32, y: i32 },
+    DoorClosed { x: i32, y: i32 },
+    Equipped { name: String, slot: Slot },
+    Unequipped { name: String, slot: Slot },
+    /// The player has enough experience for the given character level.
+    LevelUpReady { level: i32 },
+    LeveledUp { level: i32 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum PlayerAction {
+    TookTurn,
+    DidntTakeTurn,
+}
+
+/// Running totals for the current game, for the character sheet and the
+/// game-over screen.
+#[derive(Default, Serialize, Deserialize)]
+pub struct Stats {
+    /// Player turns taken, not counting free actions like menus.
+    pub turns: u32,
+    /// Monsters killed, by name.
+    pub kills: BTreeMap<String, u32>,
+    pub cause_of_death: Option<String>,
+}
+
+impl Stats {
+    pub fn total_kills(&self) -> u32 {
+        self.kills.values().sum()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Game {
+    pub map: Map,
+    pub inventory: Vec<Object>,
+    pub messages: Messages,
+    pub seed: u64,
+    pub dungeon_level: u32,
+    /// Ticks of game time since the start, see `TURN_ENERGY`.
+    pub time: u64,
+    pub stats: Stats,
+    pub(crate) rng: Pcg32,
+    /// Not saved: front ends set it again after loading, like the FOV.
+    #[serde(skip, default = "Templates::builtin")]
+    pub templates: Templates,
+    #[serde(skip)]
+    pub fov: Fov,
+    #[serde(skip)]
+    pub(crate) events: Vec<Event>,
+}
+
+/// Starts a game with the monsters and items built into the library.
+pub fn new_game(seed: u64) -> (Game, Vec<Object>) {
+    new_game_with(seed, Templates::builtin())
+}
+
+/// Starts a game that spawns monsters and items from `templates`.
+pub fn new_game_with(seed: u64, templates: Templates) -> (Game, Vec<Object>) {
+    let mut player = Object::new(0, 0, '@', "player", WHITE, true);
+    player.alive = true;
+    player.energy = TURN_ENERGY;
+    player.fighter = Some(Fighter {
+        max_hp: 30,
+        hp: 30,
+        defense: 2,
+        power: 5,
+        xp: 0,
+        speed: NORMAL_SPEED,
+        on_death: DeathCallback::Player,
+    });
+
+    let mut objects = vec![player];
+    let mut rng = Pcg32::seed_from_u64(seed);
+
+    let mut game = Game {
+        map: make_map(&mut objects, 1, &templates, &mut rng),
+        messages: Messages::new(),
+        inventory: vec![],
+        seed,
+        dungeon_level: 1,
+        time: 0,
+        stats: Stats::default(),
+        rng,
+        templates,
+        fov: Fov::new(MAP_WIDTH, MAP_HEIGHT),
+        events: vec![],
+    };
+
+    game.messages.add("Welcome, gl hf!", RED);
+    game.messages.add(format!("Dungeon seed: {}", seed), WHITE);
+    game.compute_fov(&objects);
+
+    (game, objects)
+}
+
+impl Game {
+    /// Runs one player command and, if it took a turn, lets game time pass
+    /// until the player may act again, with every monster acting whenever
+    /// it has the energy to. Returns the events produced along the way.
+    pub fn apply(&mut self, command: Command, objects: &mut Vec<Object>) -> Vec<Event> {
+        let was_ready = self.can_level_up(objects);
+        let action = if objects[PLAYER].alive {
+            match command {
+                Command::Move { dx, dy } => self.player_move_or_attack(objects, dx, dy),
+                Command::Wait => self.player_move_or_attack(objects, 0, 0),
+                Command::PickUp => self.pick_up(objects),
+                Command::UseItem(inventory_id, target) => {
+                    self.use_item(inventory_id, target, objects)
+                }
+                Command::Descend => self.descend(objects),
+                Command::Equip(inventory_id) => self.equip(inventory_id),
+                Command::Unequip(inventory_id) => self.unequip(inventory_id, objects),
+                Command::Drop(inventory_id) => self.drop_item(inventory_id, objects),
+                Command::LevelUp(choice) => self.level_up(choice, objects),
+                Command::CloseDoor { dx, dy } => self.close_door(objects, dx, dy),
+            }
+        } else {
+            PlayerAction::DidntTakeTurn
+        };
+
+        if action == PlayerAction::TookTurn {
+            self.stats.turns += 1;
+            objects[PLAYER].energy -= energy_cost(command);
+            self.compute_fov(objects);
+            self.run_until_player_ready(objects);
+        }
+
+        if !was_ready && self.can_level_up(objects) {
+            self.messages.add(
+                "Your battle skills grow stronger! You reached a new level!",
+                LIGHT_YELLOW,
+            );
+            self.events.push(Event::LevelUpReady {
+                level: objects[PLAYER].level + 1,
+            });
+        }
+
+        self.events.drain(..).collect()
+    }
+
+    /// Advances game time tick by tick. Every living fighter gains its
+    /// speed in energy and each monster with enough of it acts, in object
+    /// order, until the player has enough energy for their next action.
+    fn run_until_player_ready(&mut self, objects: &mut [Object]) {
+        while objects[PLAYER].alive && objects[PLAYER].energy < TURN_ENERGY {
+            self.time += 1;
+            if self.time % TICKS_PER_TURN == 0 {
+                self.tick_status_effects(objects);
+            }
+
+            for object in objects.iter_mut() {
+                if let Some(fighter) = object.fighter {
+                    object.energy += fighter.speed;
+                }
+            }
+
+            for id in 0..objects.len() {
+                // Monsters only ever move or attack, which cost the same.
+                while objects[PLAYER].alive
+                    && objects[id].ai.is_some()
+                    && objects[id].energy >= TURN_ENERGY
+                {
+                    ai_take_turn(id, objects, self);
+                    objects[id].energy -= ACTION_COST;
+                }
+            }
+        }
+    }
+
+    /// Counts down timed effects once per game turn, however many actions
+    /// the affected monsters got in the meantime.
+    fn tick_status_effects(&mut self, objects: &mut [Object]) {
+        for object in objects.iter_mut() {
+            let expired = match object.ai.as_mut() {
+                Some(Ai::Confused { num_turns, .. }) => {
+                    *num_turns -= 1;
+                    *num_turns < 0
+                }
+                _ => false,
+            };
+
+            if expired {
+                if let Some(Ai::Confused { previous_ai, .. }) = object.ai.take() {
+                    object.ai = Some(*previous_ai);
+                    self.messages.add(
+                        format!("The {} is no longer confused!", object.name),
+                        RED,
+                    );
+                }
+            }
+        }
+    }
+
+    /// Experience the player needs to reach the next character level.
+    pub fn xp_to_level_up(&self, objects: &[Object]) -> i32 {
+        LEVEL_UP_BASE + objects[PLAYER].level * LEVEL_UP_FACTOR
+    }
+
+    /// True while the player has unspent experience for a level-up. Front
+    /// ends should ask for a `LevelUpChoice` until this turns false.
+    pub fn can_level_up(&self, objects: &[Object]) -> bool {
+        objects[PLAYER].alive
+            && objects[PLAYER]
+                .fighter
+                .is_some_and(|f| f.xp >= self.xp_to_level_up(objects))
+    }
+
+    /// Conditions on the player worth pointing out, like an unspent
+    /// level-up. Empty while nothing is out of the ordinary.
+    pub fn status_effects(&self, objects: &[Object]) -> Vec<String> {
+        let mut effects = vec![];
+        if self.can_level_up(objects) {
+            effects.push("ready to level up".to_owned());
+        }
+        effects
+    }
+
+    /// Recomputes what the player sees and marks it as explored.
+    pub fn compute_fov(&mut self, objects: &[Object]) {
+        let (x, y) = objects[PLAYER].pos();
+        self.fov.compute(&self.map, x, y, TORCH_RADIUS);
+
+        for (x, column) in self.map.iter_mut().enumerate() {
+            for (y, tile) in column.iter_mut().enumerate() {
+                if self.fov.is_in_fov(x as i32, y as i32) {
+                    tile.explored = true;
+                }
+            }
+        }
+    }
+
+    fn move_player_by(&mut self, objects: &mut [Object], x: i32, y: i32) {
+        self.move_object_by(PLAYER, objects, x, y);
+        let (x, y) = objects[PLAYER].pos();
+        self.events.push(Event::PlayerMoved { x, y });
+    }
+
+    fn move_object_by(&mut self, id: usize, objects: &mut [Object], x: i32, y: i32) {
+        let nx = objects[id].x + x;
+        let ny = objects[id].y + y;
+
+        if !self.is_tile_blocked(objects, nx, ny) {
+            objects[id].move_by(x, y);
+        }
+    }
+
+    fn player_move_or_attack(&mut self, objects: &mut [Object], x: i32, y: i32) -> PlayerAction {
+        if (x, y) == (0, 0) {
+            return PlayerAction::TookTurn;
+        }
+
+        let nx = objects[PLAYER].x + x;
+        let ny = objects[PLAYER].y + y;
+
+        let target_id = objects
+            .iter()
+            .position(|object| object.fighter.is_some() && object.pos() == (nx, ny));
+
+        match target_id {
+            Some(target_id) => {
+                let (player, monster) = mut_two(PLAYER, target_id, objects);
+                player.attack(monster, self);
+            }
+            None if self.map[nx as usize][ny as usize].is_closed_door() => {
+                self.open_door(PLAYER, nx, ny, objects);
+            }
+            None => {
+                self.move_player_by(objects, x, y);
+            }
+        }
+
+        PlayerAction::TookTurn
+    }
+
+    /// Opens the door at `(x, y)` for the player or a monster, letting
+    /// light through at once.
+    fn open_door(&mut self, opener: usize, x: i32, y: i32, objects: &[Object]) {
+        self.map[x as usize][y as usize].set_door_open(true);
+        if opener == PLAYER {
+            self.messages.add("You open the door.", WHITE);
+        } else if self.fov.is_in_fov(x, y) {
+            self.messages
+                .add(format!("The {} opens a door.", objects[opener].name), WHITE);
+        }
+        self.events.push(Event::DoorOpened { x, y });
+        self.compute_fov(objects);
+    }
+
+    fn close_door(&mut self, objects: &[Object], dx: i32, dy: i32) -> PlayerAction {
+        let (x, y) = (objects[PLAYER].x + dx, objects[PLAYER].y + dy);
+        let open_door = (dx, dy) != (0, 0)
+            && x >= 0
+            && y >= 0
+            && x < MAP_WIDTH
+            && y < MAP_HEIGHT
+            && self.map[x as usize][y as usize].is_open_door();
+        if !open_door {
+            self.messages.add("There is no open door there.", WHITE);
+            return PlayerAction::DidntTakeTurn;
+        }
+        if objects.iter().any(|object| object.pos() == (x, y)) {
+            self.messages.add("Something is in the way.", WHITE);
+            return PlayerAction::DidntTakeTurn;
+        }
+
+        self.map[x as usize][y as usize].set_door_open(false);
+        self.messages.add("You close the door.", WHITE);
+        self.events.push(Event::DoorClosed { x, y });
+        PlayerAction::TookTurn
+    }
+
+    fn is_tile_blocked(&self, objects: &[Object], x: i32, y: i32) -> bool {
+        is_blocked(x, y, &self.map, objects)
+    }
+
+    fn pick_up(&mut self, objects: &mut Vec<Object>) -> PlayerAction {
+        let item_id = objects
+            .iter()
+            .position(|object| object.pos() == objects[PLAYER].pos() && object.item.is_some());
+        if let Some(item_id) = item_id {
+            self.pick_item_up(item_id, objects);
+        };
+        PlayerAction::TookTurn
+    }
+
+    fn pick_item_up(&mut self, object_id: usize, objects: &mut Vec<Object>) {
+        let stack = self
+            .inventory
+            .iter()
+            .position(|item| item.stacks_with(&objects[object_id]));
+
+        if stack.is_none() && self.inventory.len() >= 26 {
+            self.messages.add(
+                format!(
+                    "Your inventory is full, cannot pick up {}.",
+                    objects[object_id].name
+                ),
+                RED,
+            );
+            self.events.push(Event::InventoryFull {
+                name: objects[object_id].name.clone(),
+            });
+        } else {
+            let item = objects.swap_remove(object_id);
+            self.messages
+                .add(format!("You picked up a {}!", item.name), GREEN);
+            self.events.push(Event::PickedUp {
+                name: item.name.clone(),
+            });
+            match stack {
+                Some(stack) => self.inventory[stack].count += item.count,
+                None => self.inventory.push(item),
+            }
+        }
+    }
+
+    /// Takes a single item out of the inventory, splitting it off its stack
+    /// when there are more of the same.
+    fn take_one(&mut self, inventory_id: usize) -> Object {
+        let stack = &mut self.inventory[inventory_id];
+        if stack.count > 1 {
+            stack.count -= 1;
+            let mut item = stack.clone();
+            item.count = 1;
+            item
+        } else {
+            self.inventory.remove(inventory_id)
+        }
+    }
+
+    fn drop_item(&mut self, inventory_id: usize, objects: &mut Vec<Object>) -> PlayerAction {
+        if inventory_id >= self.inventory.len() {
+            return PlayerAction::DidntTakeTurn;
+        }
+
+        if self.inventory[inventory_id]
+            .equipment
+            .is_some_and(|e| e.equipped)
+        {
+            self.unequip(inventory_id, objects);
+        }
+
+        let mut item = self.take_one(inventory_id);
+        item.set_pos(objects[PLAYER].x, objects[PLAYER].y);
+        self.messages
+            .add(format!("You dropped a {}.", item.name), YELLOW);
+        self.events.push(Event::Dropped {
+            name: item.name.clone(),
+        });
+        objects.push(item);
+
+        PlayerAction::TookTurn
+    }
+
+    fn descend(&mut self, objects: &mut Vec<Object>) -> PlayerAction {
+        let on_stairs = objects
+            .iter()
+            .any(|object| object.pos() == objects[PLAYER].pos() && object.name == STAIRS);
+
+        if !on_stairs {
+            self.messages.add("There are no stairs here.", WHITE);
+            return PlayerAction::DidntTakeTurn;
+        }
+
+        // Monsters on the new level get no free hit on the way in.
+        self.next_level(objects);
+        PlayerAction::DidntTakeTurn
+    }
+
+    /// Replaces the current level with a fresh, deeper one. The player
+    /// object and the inventory carry over unchanged.
+    fn next_level(&mut self, objects: &mut Vec<Object>) {
+        self.dungeon_level += 1;
+        self.messages.add(
+            format!(
+                "You descend deeper into the heart of the dungeon, level {}.",
+                self.dungeon_level
+            ),
+            RED,
+        );
+
+        objects.truncate(PLAYER + 1);
+        self.map = make_map(objects, self.dungeon_level, &self.templates, &mut self.rng);
+        self.compute_fov(objects);
+        self.events.push(Event::Descended {
+            level: self.dungeon_level,
+        });
+    }
+
+    fn equip(&mut self, inventory_id: usize) -> PlayerAction {
+        let equipment = match self.inventory.get(inventory_id).and_then(|o| o.equipment) {
+            Some(equipment) => equipment,
+            None => return PlayerAction::DidntTakeTurn,
+        };
+        if equipment.equipped {
+            return PlayerAction::DidntTakeTurn;
+        }
+
+        if let Some(current) = self.get_equipped_in_slot(equipment.slot) {
+            self.set_equipped(current, false);
+        }
+        self.set_equipped(inventory_id, true);
+
+        PlayerAction::TookTurn
+    }
+
+    fn unequip(&mut self, inventory_id: usize, objects: &mut [Object]) -> PlayerAction {
+        let equipped = self
+            .inventory
+            .get(inventory_id)
+            .and_then(|o| o.equipment)
+            .is_some_and(|e| e.equipped);
+        if !equipped {
+            return PlayerAction::DidntTakeTurn;
+        }
+
+        self.set_equipped(inventory_id, false);
+
+        // Losing a max HP bonus must not leave the player above the new cap.
+        let max_hp = objects[PLAYER].max_hp(self);
+        if let Some(fighter) = objects[PLAYER].fighter.as_mut() {
+            fighter.hp = cmp::min(fighter.hp, max_hp);
+        }
+
+        PlayerAction::TookTurn
+    }
+
+    fn set_equipped(&mut self, inventory_id: usize, equipped: bool) {
+        let item = &mut self.inventory[inventory_id];
+        if let Some(equipment) = item.equipment.as_mut() {
+            equipment.equipped = equipped;
+            let name = item.name.clone();
+            let slot = equipment.slot;
+            if equipped {
+                self.messages
+                    .add(format!("Equipped {} on {}.", name, slot), LIGHT_GREEN);
+                self.events.push(Event::Equipped { name, slot });
+            } else {
+                self.messages
+                    .add(format!("Unequipped {} from {}.", name, slot), LIGHT_YELLOW);
+                self.events.push(Event::Unequipped { name, slot });
+            }
+        }
+    }
+
+    pub fn get_equipped_in_slot(&self, slot: Slot) -> Option<usize> {
+        self.inventory.iter().position(|item| {
+            item.equipment
+                .is_some_and(|e| e.equipped && e.slot == slot)
+        })
+    }
+
+    fn level_up(&mut self, choice: LevelUpChoice, objects: &mut [Object]) -> PlayerAction {
+        if !self.can_level_up(objects) {
+            return PlayerAction::DidntTakeTurn;
+        }
+
+        let xp_needed = self.xp_to_level_up(objects);
+        let player = &mut objects[PLAYER];
+        player.level += 1;
+
+        if let Some(fighter) = player.fighter.as_mut() {
+            fighter.xp -= xp_needed;
+            match choice {
+                LevelUpChoice::MaxHp => {
+                    fighter.max_hp += LEVEL_UP_HP;
+                    fighter.hp += LEVEL_UP_HP;
+                }
+                LevelUpChoice::Power => fighter.power += 1,
+                LevelUpChoice::Defense => fighter.defense += 1,
+            }
+        }
+
+        self.messages
+            .add(format!("You reached level {}!", player.level), LIGHT_YELLOW);
+        self.events.push(Event::LeveledUp {
+            level: player.level,
+        });
+
+        PlayerAction::DidntTakeTurn
+    }
+
+    /// True if a targeted item may be aimed at this tile: the player has to
+    /// see it and it has to be within the item's range.
+    pub fn in_target_range(&self, objects: &[Object], x: i32, y: i32, max_range: i32) -> bool {
+        self.fov.is_in_fov(x, y) && objects[PLAYER].distance(x, y) <= max_range as f32
+    }
+
+    /// The tiles a blast centred on `(x, y)` reaches: those within `radius`
+    /// that have a clear line to the centre, so walls shelter what is
+    /// behind them.
+    pub fn blast_area(&self, x: i32, y: i32, radius: i32) -> Fov {
+        let mut area = Fov::new(MAP_WIDTH, MAP_HEIGHT);
+        area.compute(&self.map, x, y, radius);
+        area
+    }
+
+    fn use_item(
+        &mut self,
+        inventory_id: usize,
+        target: Option<(i32, i32)>,
+        objects: &mut [Object],
+    ) -> PlayerAction {
+        use Item::*;
+
+        if inventory_id >= self.inventory.len() {
+            return PlayerAction::DidntTakeTurn;
+        }
+
+        let name = self.inventory[inventory_id].name.clone();
+        if let Some(item) = self.inventory[inventory_id].item {
+            let on_use = match item {
+                Heal => cast_heal,
+                Lightning => cast_lightning,
+                Confuse => cast_confuse,
+                Fireball => cast_fireball,
+                Equipment => toggle_equipment,
+            };
+            match on_use(inventory_id, target, self, objects) {
+                UseResult::UsedUp => {
+                    self.take_one(inventory_id);
+                    self.events.push(Event::ItemUsed { name });
+                }
+                UseResult::Kept => {}
+                UseResult::Cancelled => {
+                    self.messages.add("Cancelled", WHITE);
+                    self.events.push(Event::ItemCancelled { name });
+                    return PlayerAction::DidntTakeTurn;
+                }
+            }
+        } else {
+            self.messages
+                .add(format!("The {} cannot be used.", name), WHITE)
+        }
+
+        PlayerAction::TookTurn
+    }
+}
+
+fn ai_take_turn(monster_id: usize, objects: &mut [Object], game: &mut Game) {
+    use Ai::*;
+    if let Some(ai) = objects[monster_id].ai.take() {
+        let new_ai = match ai {
+            Basic => ai_basic(monster_id, objects, game),
+            Hunter { last_seen } => ai_hunter(monster_id, objects, game, last_seen),
+            Confused {
+                previous_ai,
+                num_turns,
+            } => ai_confused(monster_id, objects, game, previous_ai, num_turns),
+        };
+        objects[monster_id].ai = Some(new_ai);
+    }
+}
+
+fn ai_basic(monster_id: usize, objects: &mut [Object], game: &mut Game) -> Ai {
+    let (monster_x, monster_y) = objects[monster_id].pos();
+
+    if game.fov.is_in_fov(monster_x, monster_y) {
+        if objects[monster_id].distance_to(&objects[PLAYER]) >= 2.0 {
+            // A closed door in the way stops it until someone opens it.
+            let (player_x, player_y) = objects[PLAYER].pos();
+            move_towards(monster_id, player_x, player_y, &game.map, objects);
+        } else if objects[PLAYER].fighter.is_some_and(|f| f.hp > 0) {
+            let (player, monster) = mut_two(PLAYER, monster_id, objects);
+            monster.attack(player, game);
+        }
+    }
+
+    Ai::Basic
+}
+
+fn ai_hunter(
+    monster_id: usize,
+    objects: &mut [Object],
+    game: &mut Game,
+    last_seen: Option<(i32, i32)>,
+) -> Ai {
+    let (monster_x, monster_y) = objects[monster_id].pos();
+    let in_sight = game.fov.is_in_fov(monster_x, monster_y);
+    let last_seen = if in_sight {
+        Some(objects[PLAYER].pos())
+    } else {
+        last_seen
+    };
+
+    if in_sight && objects[monster_id].distance_to(&objects[PLAYER]) < 2.0 {
+        if objects[PLAYER].fighter.is_some_and(|f| f.hp > 0) {
+            let (player, monster) = mut_two(PLAYER, monster_id, objects);
+            monster.attack(player, game);
+        }
+    } else if let Some((target_x, target_y)) = last_seen {
+        if let Some((x, y)) = move_towards(monster_id, target_x, target_y, &game.map, objects) {
+            game.open_door(monster_id, x, y, objects);
+        }
+    }
+
+    // Standing where the player was last seen, with no player in sight,
+    // the trail has gone cold.
+    let last_seen = last_seen.filter(|&spot| spot != objects[monster_id].pos());
+    Ai::Hunter { last_seen }
+}
+
+/// Stumbles around at random. The confusion wears off with game time, see
+/// `Game::tick_status_effects`.
+fn ai_confused(
+    monster_id: usize,
+    objects: &mut [Object],
+    game: &mut Game,
+    previous_ai: Box<Ai>,
+    num_turns: i32,
+) -> Ai {
+    let dx = game.rng.gen_range(-1, 2);
+    let dy = game.rng.gen_range(-1, 2);
+    game.move_object_by(monster_id, objects, dx, dy);
+    Ai::Confused {
+        previous_ai,
+        num_turns,
+    }
+}
+
+/// Mutably borrow two *separate* elements from the given slice.
+/// Panics when the indexes are equal or out of bounds.
+fn mut_two<T>(first_index: usize, second_index: usize, items: &mut [T]) -> (&mut T, &mut T) {
+    assert!(first_index != second_index);
+    let split_at_index = cmp::max(first_index, second_index);
+    let (first_slice, second_slice) = items.split_at_mut(split_at_index);
+    if first_index < second_index {
+        (&mut first_slice[first_index], &mut second_slice[0])
+    } else {
+        (&mut second_slice[0], &mut first_slice[second_index])
+    }
+}
+
+/// Energy a command spends. Picking something up or dropping it is quick
+/// and lets the player act again sooner.
+fn energy_cost(command: Command) -> i32 {
+    match command {
+        Command::PickUp | Command::Drop(_) => ACTION_COST / 2,
+        _ => ACTION_COST,
+    }
+}
+
+enum UseResult {
+    UsedUp,
+    /// Used without being consumed, like putting on equipment.
+    Kept,
+    Cancelled,
+}
+
+/// How far away the player may aim an item, or `None` if it is not aimed.
+pub fn item_target_range(item: Item) -> Option<i32> {
+    match item {
+        Item::Lightning => Some(LIGHTNING_RANGE),
+        Item::Confuse => Some(CONFUSE_RANGE),
+        Item::Fireball => Some(FIREBALL_RANGE),
+        Item::Heal | Item::Equipment => None,
+    }
+}
+
+/// Radius of the area an aimed item affects around the chosen tile; zero
+/// for items that only hit what stands on it.
+pub fn item_blast_radius(item: Item) -> i32 {
+    match item {
+        Item::Fireball => FIREBALL_RADIUS,
+        _ => 0,
+    }
+}
+
+pub fn monster_at(objects: &[Object], x: i32, y: i32) -> Option<usize> {
+    objects.iter().position(|object| {
+        object.pos() == (x, y) && object.fighter.is_some() && object.ai.is_some()
+    })
+}
+
+/// Resolves the tile picked for a targeted item to the monster standing
+/// there, telling the player why when there is none.
+fn target_monster(
+    target: Option<(i32, i32)>,
+    max_range: i32,
+    game: &mut Game,
+    objects: &[Object],
+) -> Option<usize> {
+    let (x, y) = target?;
+
+    if !game.in_target_range(objects, x, y, max_range) {
+        game.messages.add("That is out of range.", RED);
+        return None;
+    }
+
+    let monster_id = monster_at(objects, x, y);
+    if monster_id.is_none() {
+        game.messages.add("There is no enemy there.", RED);
+    }
+    monster_id
+}
+
+fn toggle_equipment(
+    inventory_id: usize,
+    _target: Option<(i32, i32)>,
+    game: &mut Game,
+    objects: &mut [Object],
+) -> UseResult {
+    let equipped = game.inventory[inventory_id]
+        .equipment
+        .is_some_and(|e| e.equipped);
+    if equipped {
+        game.unequip(inventory_id, objects);
+    } else {
+        game.equip(inventory_id);
+    }
+    UseResult::Kept
+}
+
+fn cast_heal(
+    _inventory_id: usize,
+    _target: Option<(i32, i32)>,
+    game: &mut Game,
+    objects: &mut [Object],
+) -> UseResult {
+    if let Some(fighter) = objects[PLAYER].fighter {
+        if fighter.hp == objects[PLAYER].max_hp(game) {
+            game.messages.add("You are alraedy at full health.", RED);
+            return UseResult::Cancelled;
+        }
+
+        game.messages.add("Your wounds are closing!", LIGHT_VIOLET);
+        objects[PLAYER].heal(HEAL_AMOUNT, game);
+        return UseResult::UsedUp;
+    }
+
+    UseResult::Cancelled
+}
+
+fn cast_lightning(
+    _inventory_id: usize,
+    target: Option<(i32, i32)>,
+    game: &mut Game,
+    objects: &mut [Object],
+) -> UseResult {
+    let monster_id = target_monster(target, LIGHTNING_RANGE, game, objects);
+    if let Some(monster_id) = monster_id {
+        game.messages.add(
+            format!(
+                "A lightting bolt strikes the {} with a loud thunder! for {} hit points.",
+                objects[monster_id].name, LIGHTNING_DAMAGE,
+            ),
+            LIGHT_BLUE,
+        );
+        if let Some(xp) = objects[monster_id].take_damage(LIGHTNING_DAMAGE, game) {
+            objects[PLAYER].gain_xp(xp);
+        }
+        UseResult::UsedUp
+    } else {
+        UseResult::Cancelled
+    }
+}
+
+/// Nearest visible monster within range, used to place the targeting
+/// cursor somewhere useful.
+pub fn closest_monster(game: &Game, objects: &[Object], max_range: i32) -> Option<usize> {
+    let mut closest_enemy = None;
+    let mut closest_dist = (max_range + 1) as f32;
+
+    for (id, object) in objects.iter().enumerate() {
+        if (id != PLAYER)
+            && object.fighter.is_some()
+            && object.ai.is_some()
+            && game.fov.is_in_fov(object.x, object.y)
+        {
+            let dist = objects[PLAYER].distance_to(object);
+            if dist < closest_dist {
+                closest_enemy = Some(id);
+                closest_dist = dist;
+            }
+        }
+    }
+
+    closest_enemy
+}
+
+fn cast_confuse(
+    _inventory_id: usize,
+    target: Option<(i32, i32)>,
+    game: &mut Game,
+    objects: &mut [Object],
+) -> UseResult {
+    let monster_id = target_monster(target, CONFUSE_RANGE, game, objects);
+    if let Some(monster_id) = monster_id {
+        game.messages.add(
+            format!("{} looks confused", objects[monster_id].name,),
+            LIGHT_GREEN,
+        );
+        let old_ai = objects[monster_id].ai.take().unwrap_or(Ai::Basic);
+        objects[monster_id].ai = Some(Ai::Confused {
+            previous_ai: Box::new(old_ai),
+            num_turns: CONFUSE_NUM_TURNS,
+        });
+        UseResult::UsedUp
+    } else {
+        UseResult::Cancelled
+    }
+}
+
+fn cast_fireball(
+    _inventory_id: usize,
+    target: Option<(i32, i32)>,
+    game: &mut Game,
+    objects: &mut [Object],
+) -> UseResult {
+    let (x, y) = match target {
+        Some(target) => target,
+        None => return UseResult::Cancelled,
+    };
+
+    if !game.in_target_range(objects, x, y, FIREBALL_RANGE) {
+        game.messages.add("That is out of range.", RED);
+        return UseResult::Cancelled;
+    }
+
+    game.messages.add(
+        format!(
+            "The fireball explodes, burning everything within {} tiles!",
+            FIREBALL_RADIUS
+        ),
+        ORANGE,
+    );
+
+    let blast = game.blast_area(x, y, FIREBALL_RADIUS);
+    let victims: Vec<usize> = objects
+        .iter()
+        .enumerate()
+        .filter(|(_, object)| object.fighter.is_some() && blast.is_in_fov(object.x, object.y))
+        .map(|(id, _)| id)
+        .collect();
+
+    let mut xp_gained = 0;
+    for id in victims {
+        let name = objects[id].name.clone();
+        let killed = objects[id].take_damage_quietly(FIREBALL_DAMAGE, game);
+        let (message, color) = match (id == PLAYER, killed.is_some()) {
+            (true, false) => (
+                format!("You get burned for {} hit points.", FIREBALL_DAMAGE),
+                ORANGE,
+            ),
+            (true, true) => (
+                format!("You are burned to death ({} damage).", FIREBALL_DAMAGE),
+                RED,
+            ),
+            (false, false) => (
+                format!("The {} gets burned for {} hit points.", name, FIREBALL_DAMAGE),
+                ORANGE,
+            ),
+            (false, true) => (
+                format!("The {} is burned to death ({} damage).", name, FIREBALL_DAMAGE),
+                ORANGE,
+            ),
+        };
+        game.messages.add(message, color);
+
+        if let Some(xp) = killed {
+            if id == PLAYER {
+                game.stats.cause_of_death = Some("burned by their own fireball".into());
+            } else {
+                xp_gained += xp;
+            }
+        }
+    }
+    objects[PLAYER].gain_xp(xp_gained);
+
+    UseResult::UsedUp
+}
@@ -1,7 +1,11 @@
+use super::colors::{Color, WHITE};
+use super::mapgen::{connect_unreachable, farthest_floor, flood_fill, Layout};
 use super::object::{place_objects, Object};
-use rand::Rng;
-use std::cmp;
-use tcod::colors::*;
+use super::templates::Templates;
+use super::vault::place_vaults;
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg32;
+use serde::{Deserialize, Serialize};
 
 pub const MAP_WIDTH: i32 = 80;
 pub const MAP_HEIGHT: i32 = 43;
@@ -24,15 +28,21 @@ const COLOR_LIGHT_GROUND: Color = Color {
     b: 50,
 };
 
-const ROOM_MAX_SIZE: i32 = 10;
-const ROOM_MIN_SIZE: i32 = 6;
-const MAX_ROOMS: i32 = 30;
+const COLOR_DOOR: Color = Color {
+    r: 160,
+    g: 100,
+    b: 40,
+};
+
+pub const STAIRS: &str = "stairs";
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct Tile {
     pub blocked: bool,
     pub explored: bool,
     pub block_sight: bool,
+    /// A door, which blocks movement and sight while it is closed.
+    pub door: bool,
 }
 
 impl Tile {
@@ -41,6 +51,7 @@ impl Tile {
             blocked: false,
             explored: false,
             block_sight: false,
+            door: false,
         }
     }
 
@@ -49,6 +60,42 @@ impl Tile {
             blocked: true,
             explored: false,
             block_sight: true,
+            door: false,
+        }
+    }
+
+    /// A closed door.
+    pub fn door() -> Self {
+        Tile {
+            door: true,
+            ..Tile::wall()
+        }
+    }
+
+    pub fn is_closed_door(&self) -> bool {
+        self.door && self.blocked
+    }
+
+    pub fn is_open_door(&self) -> bool {
+        self.door && !self.blocked
+    }
+
+    pub fn set_door_open(&mut self, open: bool) {
+        self.blocked = !open;
+        self.block_sight = !open;
+    }
+
+    /// Floor, or a door that can be opened on the way through.
+    pub fn is_walkable(&self) -> bool {
+        !self.blocked || self.door
+    }
+
+    /// The character drawn over the tile's background, if any.
+    pub fn glyph(&self) -> Option<(char, Color)> {
+        match (self.door, self.blocked) {
+            (true, true) => Some(('+', COLOR_DOOR)),
+            (true, false) => Some(('\'', COLOR_DOOR)),
+            (false, _) => None,
         }
     }
 
@@ -67,60 +114,108 @@ impl Tile {
 
 pub type Map = Vec<Vec<Tile>>;
 
-pub fn make_map(objects: &mut Vec<Object>) -> Map {
-    let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
-
-    let mut rooms = vec![];
-
-    let mut player_x = 0;
-    let mut player_y = 0;
+/// Levels that are still broken after `connect_unreachable` are thrown
+/// away and generated again, at most this many times.
+const MAX_LEVEL_ATTEMPTS: u32 = 10;
+
+/// Makes a new level and fills it with monsters, items and the stairs. Any
+/// objects but the player, `objects[0]`, are dropped and the player moves
+/// to the start, from where every floor tile and object can be walked to.
+pub fn make_map(
+    objects: &mut Vec<Object>,
+    level: u32,
+    templates: &Templates,
+    rng: &mut impl Rng,
+) -> Map {
+    make_map_with(objects, level, rng, |objects, rng| {
+        lay_out_level(objects, level, templates, rng)
+    })
+}
 
-    for _ in 0..MAX_ROOMS {
-        let w = rand::thread_rng().gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1);
-        let h = rand::thread_rng().gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1);
-        let x = rand::thread_rng().gen_range(0, MAP_WIDTH - w);
-        let y = rand::thread_rng().gen_range(0, MAP_HEIGHT - h);
+/// `make_map` with the level laid out by `lay_out`, which places the player
+/// and everything else. Each attempt gets a generator seeded from `rng`.
+/// Panics, naming the seed of the last attempt, if no attempt gives a level
+/// where everything can be reached.
+pub fn make_map_with(
+    objects: &mut Vec<Object>,
+    level: u32,
+    rng: &mut impl Rng,
+    mut lay_out: impl FnMut(&mut Vec<Object>, &mut Pcg32) -> Map,
+) -> Map {
+    let mut seed = 0;
+    let mut unreachable = vec![];
+    for _ in 0..MAX_LEVEL_ATTEMPTS {
+        seed = rng.gen();
+        objects.truncate(1);
+        let mut map = lay_out(objects, &mut Pcg32::seed_from_u64(seed));
+
+        if !unreachable_spots(&map, objects).is_empty() {
+            connect_unreachable(&mut map, objects[0].pos());
+        }
+        unreachable = unreachable_spots(&map, objects);
+        if unreachable.is_empty() {
+            return map;
+        }
+    }
 
-        let room = Rect::new(x, y, w, h);
-        let failed = rooms
-            .iter()
-            .any(|other_room| room.intersects_with(other_room));
+    panic!(
+        "level {} still has spots that cannot be reached after {} attempts, the last from \
+         seed {}: {:?}",
+        level, MAX_LEVEL_ATTEMPTS, seed, unreachable
+    );
+}
 
-        if !failed {
-            let (new_x, new_y) = room.center();
+/// Lays out a level with the generator `templates` pick for it, stamps in
+/// any vaults, then fills its rooms or other regions with the player,
+/// monsters, items and stairs.
+fn lay_out_level(
+    objects: &mut Vec<Object>,
+    level: u32,
+    templates: &Templates,
+    rng: &mut impl Rng,
+) -> Map {
+    let Layout { mut map, regions } = templates.generator.at(level).generate(rng);
+
+    let (player_x, player_y) = regions[0].center;
+    objects[0].x = player_x;
+    objects[0].y = player_y;
 
-            create_room(&room, &mut map);
+    place_vaults(&mut map, regions[0].center, objects, level, templates, rng);
 
-            match rooms.last() {
-                Some(&ref prev_room) => {
-                    let (prev_x, prev_y) = prev_room.center();
+    for region in &regions {
+        place_objects(region, &map, objects, level, templates, rng);
+    }
 
-                    if rand::random() {
-                        create_h_tunnel(prev_x, new_x, prev_y, &mut map);
-                        create_v_tunnel(prev_y, new_y, new_x, &mut map);
-                    } else {
-                        create_v_tunnel(prev_y, new_y, prev_x, &mut map);
-                        create_h_tunnel(prev_x, new_x, new_y, &mut map);
-                    }
-                }
-                None => {
-                    player_x = new_x;
-                    player_y = new_y;
-                }
-            }
+    // With a single region its center is where the player starts.
+    let (stairs_x, stairs_y) = match regions.len() {
+        1 => farthest_floor(&map, regions[0].center),
+        n => regions[n - 1].center,
+    };
+    let mut stairs = Object::new(stairs_x, stairs_y, '>', STAIRS, WHITE, false);
+    stairs.always_visible = true;
+    objects.push(stairs);
 
-            place_objects(&room, &map, objects);
+    map
+}
 
-            rooms.push(room);
-        }
+/// Floor tiles and object positions that cannot be walked to from the
+/// player, `objects[0]`. Empty for every level `make_map` returns.
+pub fn unreachable_spots(map: &Map, objects: &[Object]) -> Vec<(i32, i32)> {
+    let mut reachable = vec![vec![false; MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+    for (x, y) in flood_fill(map, objects[0].pos()) {
+        reachable[x as usize][y as usize] = true;
     }
 
-    objects[0].x = player_x;
-    objects[0].y = player_y;
-
-    map
+    let floor = (0..MAP_WIDTH)
+        .flat_map(|x| (0..MAP_HEIGHT).map(move |y| (x, y)))
+        .filter(|&(x, y)| map[x as usize][y as usize].is_walkable());
+    floor
+        .chain(objects.iter().map(Object::pos))
+        .filter(|&(x, y)| !reachable[x as usize][y as usize])
+        .collect()
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Rect {
     pub x1: i32,
     pub y1: i32,
@@ -152,27 +247,7 @@ impl Rect {
     }
 }
 
-fn create_room(room: &Rect, map: &mut Map) {
-    for x in (room.x1 + 1)..room.x2 {
-        for y in (room.y1 + 1)..room.y2 {
-            map[x as usize][y as usize] = Tile::empty();
-        }
-    }
-}
-
-fn create_h_tunnel(x1: i32, x2: i32, y: i32, map: &mut Map) {
-    for x in cmp::min(x1, x2)..(cmp::max(x1, x2) + 1) {
-        map[x as usize][y as usize] = Tile::empty();
-    }
-}
-
-fn create_v_tunnel(y1: i32, y2: i32, x: i32, map: &mut Map) {
-    for y in cmp::min(y1, y2)..(cmp::max(y1, y2) + 1) {
-        map[x as usize][y as usize] = Tile::empty();
-    }
-}
-
-pub fn is_blocked(x: i32, y: i32, map: &Map, objects: &Vec<Object>) -> bool {
+pub fn is_blocked(x: i32, y: i32, map: &Map, objects: &[Object]) -> bool {
     if map[x as usize][y as usize].blocked {
         return true;
     }
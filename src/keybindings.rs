@@ -0,0 +1,421 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+
+pub const BINDINGS_FILE: &str = "keybindings.toml";
+
+/// Everything a key can be bound to outside of menus.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Action {
+    MoveNorth,
+    MoveSouth,
+    MoveWest,
+    MoveEast,
+    MoveNorthWest,
+    MoveNorthEast,
+    MoveSouthWest,
+    MoveSouthEast,
+    Wait,
+    PickUp,
+    Inventory,
+    Drop,
+    CloseDoor,
+    Descend,
+    CharacterSheet,
+    MessageLog,
+    ShowBindings,
+    ToggleFullscreen,
+    Quit,
+}
+
+const ACTIONS: [Action; 19] = [
+    Action::MoveNorth,
+    Action::MoveSouth,
+    Action::MoveWest,
+    Action::MoveEast,
+    Action::MoveNorthWest,
+    Action::MoveNorthEast,
+    Action::MoveSouthWest,
+    Action::MoveSouthEast,
+    Action::Wait,
+    Action::PickUp,
+    Action::Inventory,
+    Action::Drop,
+    Action::CloseDoor,
+    Action::Descend,
+    Action::CharacterSheet,
+    Action::MessageLog,
+    Action::ShowBindings,
+    Action::ToggleFullscreen,
+    Action::Quit,
+];
+
+impl Action {
+    /// Name used for the action in the bindings file.
+    pub fn name(self) -> &'static str {
+        use Action::*;
+        match self {
+            MoveNorth => "move_north",
+            MoveSouth => "move_south",
+            MoveWest => "move_west",
+            MoveEast => "move_east",
+            MoveNorthWest => "move_north_west",
+            MoveNorthEast => "move_north_east",
+            MoveSouthWest => "move_south_west",
+            MoveSouthEast => "move_south_east",
+            Wait => "wait",
+            PickUp => "pick_up",
+            Inventory => "inventory",
+            Drop => "drop",
+            CloseDoor => "close_door",
+            Descend => "descend",
+            CharacterSheet => "character_sheet",
+            MessageLog => "message_log",
+            ShowBindings => "show_bindings",
+            ToggleFullscreen => "toggle_fullscreen",
+            Quit => "quit",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Action> {
+        ACTIONS.iter().copied().find(|action| action.name() == name)
+    }
+
+    /// Movement offset for the move actions, `(0, 0)` for waiting.
+    pub fn direction(self) -> Option<(i32, i32)> {
+        use Action::*;
+        match self {
+            MoveNorth => Some((0, -1)),
+            MoveSouth => Some((0, 1)),
+            MoveWest => Some((-1, 0)),
+            MoveEast => Some((1, 0)),
+            MoveNorthWest => Some((-1, -1)),
+            MoveNorthEast => Some((1, -1)),
+            MoveSouthWest => Some((-1, 1)),
+            MoveSouthEast => Some((1, 1)),
+            Wait => Some((0, 0)),
+            _ => None,
+        }
+    }
+
+    fn default_keys(self) -> &'static [&'static str] {
+        use Action::*;
+        match self {
+            MoveNorth => &["Up", "NumPad8", "k"],
+            MoveSouth => &["Down", "NumPad2", "j"],
+            MoveWest => &["Left", "NumPad4", "h"],
+            MoveEast => &["Right", "NumPad6", "l"],
+            MoveNorthWest => &["Home", "NumPad7", "y"],
+            MoveNorthEast => &["PageUp", "NumPad9", "u"],
+            MoveSouthWest => &["End", "NumPad1", "b"],
+            MoveSouthEast => &["PageDown", "NumPad3", "n"],
+            Wait => &["NumPad5", "."],
+            PickUp => &["g"],
+            Inventory => &["i"],
+            Drop => &["d"],
+            CloseDoor => &["C"],
+            Descend => &[">"],
+            CharacterSheet => &["c"],
+            MessageLog => &["m"],
+            ShowBindings => &["?"],
+            ToggleFullscreen => &["Alt+Enter"],
+            Quit => &["Escape"],
+        }
+    }
+}
+
+/// Keys bound by name rather than by the character they type.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NamedKey {
+    Escape,
+    Enter,
+    Tab,
+    Backspace,
+    Space,
+    Insert,
+    Delete,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Up,
+    Down,
+    Left,
+    Right,
+    NumPad0,
+    NumPad1,
+    NumPad2,
+    NumPad3,
+    NumPad4,
+    NumPad5,
+    NumPad6,
+    NumPad7,
+    NumPad8,
+    NumPad9,
+    NumPadEnter,
+    F1,
+    F2,
+    F3,
+    F4,
+    F5,
+    F6,
+    F7,
+    F8,
+    F9,
+    F10,
+    F11,
+}
+
+const NAMED_KEYS: [(&str, NamedKey); 37] = [
+    ("Escape", NamedKey::Escape),
+    ("Enter", NamedKey::Enter),
+    ("Tab", NamedKey::Tab),
+    ("Backspace", NamedKey::Backspace),
+    ("Space", NamedKey::Space),
+    ("Insert", NamedKey::Insert),
+    ("Delete", NamedKey::Delete),
+    ("Home", NamedKey::Home),
+    ("End", NamedKey::End),
+    ("PageUp", NamedKey::PageUp),
+    ("PageDown", NamedKey::PageDown),
+    ("Up", NamedKey::Up),
+    ("Down", NamedKey::Down),
+    ("Left", NamedKey::Left),
+    ("Right", NamedKey::Right),
+    ("NumPad0", NamedKey::NumPad0),
+    ("NumPad1", NamedKey::NumPad1),
+    ("NumPad2", NamedKey::NumPad2),
+    ("NumPad3", NamedKey::NumPad3),
+    ("NumPad4", NamedKey::NumPad4),
+    ("NumPad5", NamedKey::NumPad5),
+    ("NumPad6", NamedKey::NumPad6),
+    ("NumPad7", NamedKey::NumPad7),
+    ("NumPad8", NamedKey::NumPad8),
+    ("NumPad9", NamedKey::NumPad9),
+    ("NumPadEnter", NamedKey::NumPadEnter),
+    ("F1", NamedKey::F1),
+    ("F2", NamedKey::F2),
+    ("F3", NamedKey::F3),
+    ("F4", NamedKey::F4),
+    ("F5", NamedKey::F5),
+    ("F6", NamedKey::F6),
+    ("F7", NamedKey::F7),
+    ("F8", NamedKey::F8),
+    ("F9", NamedKey::F9),
+    ("F10", NamedKey::F10),
+    ("F11", NamedKey::F11),
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum KeyName {
+    Named(NamedKey),
+    /// A printable character, matched against the text the key produced so
+    /// it follows the player's keyboard layout.
+    Text(String),
+}
+
+/// A key with the modifiers held with it, either as written in the
+/// bindings file or as pressed by the player.
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeySpec {
+    pub key: KeyName,
+    pub alt: bool,
+    pub ctrl: bool,
+}
+
+impl KeySpec {
+    /// Reads a key such as "k", "PageUp" or "Ctrl+Alt+x". Key names are not
+    /// case sensitive, single characters are.
+    pub fn parse(spec: &str) -> Option<KeySpec> {
+        let mut alt = false;
+        let mut ctrl = false;
+        let mut rest = spec;
+
+        loop {
+            if let Some(stripped) = rest.strip_prefix("Alt+") {
+                alt = true;
+                rest = stripped;
+            } else if let Some(stripped) = rest.strip_prefix("Ctrl+") {
+                ctrl = true;
+                rest = stripped;
+            } else {
+                break;
+            }
+        }
+
+        let key = if rest.chars().count() == 1 {
+            KeyName::Text(rest.to_owned())
+        } else {
+            let (_, named) = NAMED_KEYS
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(rest))?;
+            KeyName::Named(*named)
+        };
+
+        Some(KeySpec { key, alt, ctrl })
+    }
+
+    /// True if pressing `pressed` triggers this binding. Extra modifiers
+    /// held down do not stop a match.
+    pub fn matches(&self, pressed: &KeySpec) -> bool {
+        self.key == pressed.key && (!self.alt || pressed.alt) && (!self.ctrl || pressed.ctrl)
+    }
+
+    fn modifiers(&self) -> usize {
+        self.alt as usize + self.ctrl as usize
+    }
+}
+
+impl fmt::Display for KeySpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.ctrl {
+            write!(f, "Ctrl+")?;
+        }
+        if self.alt {
+            write!(f, "Alt+")?;
+        }
+        match self.key {
+            KeyName::Named(key) => {
+                let name = NAMED_KEYS
+                    .iter()
+                    .find(|(_, named)| *named == key)
+                    .map_or("?", |(name, _)| name);
+                write!(f, "{}", name)
+            }
+            KeyName::Text(ref text) => write!(f, "{}", text),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum BindingsError {
+    Io(io::Error),
+    Format(toml::de::Error),
+    UnknownAction(String),
+    UnknownKey { action: String, key: String },
+    Conflict {
+        key: String,
+        first: Action,
+        second: Action,
+    },
+}
+
+impl fmt::Display for BindingsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BindingsError::Io(e) => write!(f, "cannot read {}: {}", BINDINGS_FILE, e),
+            BindingsError::Format(e) => write!(f, "{}: {}", BINDINGS_FILE, e),
+            BindingsError::UnknownAction(action) => write!(
+                f,
+                "{}: unknown action '{}', expected one of: {}",
+                BINDINGS_FILE,
+                action,
+                ACTIONS
+                    .iter()
+                    .map(|action| action.name())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            BindingsError::UnknownKey { action, key } => write!(
+                f,
+                "{}: unknown key '{}' for action '{}'",
+                BINDINGS_FILE, key, action
+            ),
+            BindingsError::Conflict { key, first, second } => write!(
+                f,
+                "{}: key '{}' is bound to both '{}' and '{}'",
+                BINDINGS_FILE,
+                key,
+                first.name(),
+                second.name()
+            ),
+        }
+    }
+}
+
+impl Error for BindingsError {}
+
+pub struct KeyBindings {
+    bindings: Vec<(KeySpec, Action)>,
+}
+
+impl KeyBindings {
+    /// Reads the bindings file. Actions it does not mention keep their
+    /// default keys, and a missing file means all defaults.
+    pub fn load() -> Result<KeyBindings, BindingsError> {
+        match fs::read_to_string(BINDINGS_FILE) {
+            Ok(data) => KeyBindings::parse(&data),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => KeyBindings::parse(""),
+            Err(e) => Err(BindingsError::Io(e)),
+        }
+    }
+
+    /// Reads bindings from the text of a bindings file.
+    pub fn parse(data: &str) -> Result<KeyBindings, BindingsError> {
+        let overrides: BTreeMap<String, Vec<String>> =
+            toml::from_str(data).map_err(BindingsError::Format)?;
+
+        for name in overrides.keys() {
+            if Action::from_name(name).is_none() {
+                return Err(BindingsError::UnknownAction(name.clone()));
+            }
+        }
+
+        let mut bindings: Vec<(KeySpec, Action)> = vec![];
+        for &action in ACTIONS.iter() {
+            let keys: Vec<&str> = match overrides.get(action.name()) {
+                Some(keys) => keys.iter().map(|key| key.as_str()).collect(),
+                None => action.default_keys().to_vec(),
+            };
+
+            for key in keys {
+                let spec = KeySpec::parse(key).ok_or_else(|| BindingsError::UnknownKey {
+                    action: action.name().to_owned(),
+                    key: key.to_owned(),
+                })?;
+
+                match bindings.iter().find(|(bound, _)| *bound == spec) {
+                    Some(&(_, other)) if other != action => {
+                        return Err(BindingsError::Conflict {
+                            key: key.to_owned(),
+                            first: other,
+                            second: action,
+                        });
+                    }
+                    Some(_) => {}
+                    None => bindings.push((spec, action)),
+                }
+            }
+        }
+
+        Ok(KeyBindings { bindings })
+    }
+
+    /// The action bound to this key press. A binding with modifiers wins
+    /// over the same key without them, so Alt+Enter is not also Enter.
+    pub fn action_for(&self, pressed: &KeySpec) -> Option<Action> {
+        self.bindings
+            .iter()
+            .filter(|(spec, _)| spec.matches(pressed))
+            .max_by_key(|(spec, _)| spec.modifiers())
+            .map(|&(_, action)| action)
+    }
+
+    /// One line per action with every key bound to it.
+    pub fn describe(&self) -> Vec<String> {
+        ACTIONS
+            .iter()
+            .map(|&action| {
+                let keys: Vec<String> = self
+                    .bindings
+                    .iter()
+                    .filter(|&&(_, bound)| bound == action)
+                    .map(|(spec, _)| spec.to_string())
+                    .collect();
+                format!("{:<18} {}", action.name(), keys.join(", "))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,16 @@
+//! Headless roguelike simulation. Front ends feed it `Command`s and read
+//! back the `Event`s, map and objects to draw.
+
+pub mod colors;
+pub mod fov;
+pub mod game;
+pub mod gamemap;
+pub mod keybindings;
+pub mod mapgen;
+pub mod messages;
+pub mod object;
+pub mod pathfinding;
+pub mod save;
+pub mod spawn;
+pub mod templates;
+pub mod vault;
@@ -1,142 +1,241 @@
-use std::cmp;
-
 use tcod::colors::*;
 use tcod::console::*;
+use tcod::input;
 
-mod object;
-use object::*;
+use crablike::colors;
+use crablike::game::*;
+use crablike::gamemap::*;
+use crablike::keybindings::*;
+use crablike::object::{plural, Object};
+use crablike::save::*;
+use crablike::spawn::describe_spawns;
+use crablike::templates::*;
 
 mod mytcod;
 use mytcod::*;
 
-mod gamemap;
-use gamemap::*;
-
-use tcod::input::{self, Event, Key};
-use tcod::map::Map as FovMap;
-
-use rand::Rng;
-
-const PLAYER: usize = 0;
-const HEAL_AMOUNT: i32 = 4;
-const LIGHTNING_DAMAGE: i32 = 40;
-const LIGHTNING_RANGE: i32 = 5;
-const CONFUSE_RANGE: i32 = 8;
-const CONFUSE_NUM_TURNS: i32 = 10;
-
 #[derive(Clone, Copy, Debug, PartialEq)]
 enum PlayerAction {
-    TookTurn,
+    Play(Command),
     DidntTakeTurn,
     Exit,
 }
 
-pub struct Game {
-    pub map: Map,
-    inventory: Vec<Object>,
-    messages: Messages,
-}
-
-impl Game {
-    fn render_all(&mut self, tcod: &mut Tcod, objects: &Vec<Object>, fov_recompute: bool) {
-        if fov_recompute {
-            tcod.compute_fov(objects[PLAYER].x, objects[PLAYER].y);
+/// Draws the map, objects and panel onto the root console. Callers flush.
+fn render_all(tcod: &mut Tcod, game: &Game, objects: &[Object]) {
+    tcod.con.clear();
+
+    let mut to_draw: Vec<_> = objects
+        .iter()
+        .filter(|o| {
+            game.fov.is_in_fov(o.x, o.y)
+                || (o.always_visible && game.map[o.x as usize][o.y as usize].explored)
+        })
+        .collect();
+    to_draw.sort_by_key(|o| o.blocks);
+
+    for y in 0..MAP_HEIGHT {
+        for x in 0..MAP_WIDTH {
+            let visible = game.fov.is_in_fov(x, y);
+            let tile = &game.map[x as usize][y as usize];
+
+            if tile.explored {
+                let color = tcod_color(tile.color(visible));
+                tcod.con
+                    .set_char_background(x, y, color, BackgroundFlag::Set);
+                if let Some((glyph, color)) = tile.glyph() {
+                    tcod.con.set_default_foreground(tcod_color(color));
+                    tcod.con.put_char(x, y, glyph, BackgroundFlag::None);
+                }
+            }
         }
+    }
 
-        let mut to_draw: Vec<_> = objects
-            .iter()
-            .filter(|o| tcod.fov.is_in_fov(o.x, o.y))
-            .collect();
-        to_draw.sort_by(|o1, o2| o1.blocks.cmp(&o2.blocks));
+    for object in &to_draw {
+        draw_object(object, &mut tcod.con);
+    }
 
-        for object in &to_draw {
-            object.draw(&mut tcod.con);
-        }
+    draw_object(&objects[PLAYER], &mut tcod.con);
 
-        objects[PLAYER].draw(&mut tcod.con);
+    if let Some(fighter) = objects[PLAYER].fighter {
+        tcod.root.print_ex(
+            1,
+            SCREEN_HEIGHT - 2,
+            BackgroundFlag::None,
+            TextAlignment::Left,
+            format!("HP: {}/{}", fighter.hp, objects[PLAYER].max_hp(game)),
+        )
+    }
 
-        for y in 0..MAP_HEIGHT {
-            for x in 0..MAP_WIDTH {
-                let visible = tcod.fov.is_in_fov(x, y);
+    tcod.panel.set_default_background(BLACK);
+    tcod.panel.clear();
+
+    let hp = objects[PLAYER].fighter.map_or(0, |f| f.hp);
+    let max_hp = objects[PLAYER].max_hp(game);
+
+    tcod.render_bar(
+        1,
+        1,
+        BAR_WIDTH,
+        Bar {
+            name: "HP",
+            value: hp,
+            maximum: max_hp,
+            bar_color: LIGHT_RED,
+            back_color: DARKER_RED,
+        },
+    );
+
+    let xp = objects[PLAYER].fighter.map_or(0, |f| f.xp);
+    let xp_needed = game.xp_to_level_up(objects);
+
+    tcod.render_bar(
+        1,
+        2,
+        BAR_WIDTH,
+        Bar {
+            name: "XP",
+            value: xp,
+            maximum: xp_needed,
+            bar_color: LIGHT_VIOLET,
+            back_color: DARKER_VIOLET,
+        },
+    );
+
+    tcod.panel.print_ex(
+        1,
+        3,
+        BackgroundFlag::None,
+        TextAlignment::Left,
+        format!("Dungeon level: {}", game.dungeon_level),
+    );
+    tcod.panel.print_ex(
+        1,
+        4,
+        BackgroundFlag::None,
+        TextAlignment::Left,
+        format!("Character level: {}", objects[PLAYER].level),
+    );
+
+    tcod.print_messages(&game.messages);
+
+    tcod.blit_con(SCREEN_WIDTH, SCREEN_HEIGHT);
+    tcod.blit_panel(SCREEN_WIDTH, SCREEN_HEIGHT, PANEL_Y);
+}
 
-                let explored = &mut self.map[x as usize][y as usize].explored;
-                if visible {
-                    *explored = true;
-                }
+/// Lets the player pick a tile with the movement keys or the mouse. Enter or
+/// a left click confirms a tile in range, Escape or a right click cancels.
+/// A non-zero `radius` also shades the area the item would affect, leaving
+/// out tiles the blast cannot reach past walls.
+fn target_tile(
+    tcod: &mut Tcod,
+    game: &Game,
+    objects: &[Object],
+    max_range: i32,
+    radius: i32,
+) -> Option<(i32, i32)> {
+    use tcod::input::KeyCode::*;
+
+    let (mut x, mut y) = closest_monster(game, objects, max_range)
+        .map_or(objects[PLAYER].pos(), |id| objects[id].pos());
+
+    while !tcod.root.window_closed() {
+        let in_range = game.in_target_range(objects, x, y, max_range);
+
+        render_all(tcod, game, objects);
 
-                if *explored {
-                    let color = self.map[x as usize][y as usize].color(visible);
-                    tcod.con
-                        .set_char_background(x, y, color, BackgroundFlag::Set);
+        let highlight = if in_range { LIGHT_YELLOW } else { DARK_RED };
+        let blast = game.blast_area(x, y, radius);
+        for bx in (x - radius).max(0)..=(x + radius).min(MAP_WIDTH - 1) {
+            for by in (y - radius).max(0)..=(y + radius).min(MAP_HEIGHT - 1) {
+                if blast.is_in_fov(bx, by) {
+                    tcod.root
+                        .set_char_background(bx, by, DARK_ORANGE, BackgroundFlag::Set);
                 }
             }
         }
+        tcod.root
+            .set_char_background(x, y, highlight, BackgroundFlag::Set);
 
-        if let Some(fighter) = objects[PLAYER].fighter {
-            tcod.root.print_ex(
-                1,
-                SCREEN_HEIGHT - 2,
-                BackgroundFlag::None,
-                TextAlignment::Left,
-                format!("HP: {}/{}", fighter.hp, fighter.max_hp),
-            )
-        }
-    }
-
-    fn move_player_by(&mut self, objects: &mut Vec<Object>, x: i32, y: i32) {
-        self.move_object_by(PLAYER, objects, x, y);
-    }
-
-    fn move_object_by(&mut self, id: usize, objects: &mut Vec<Object>, x: i32, y: i32) {
-        let nx = objects[id].x + x;
-        let ny = objects[id].y + y;
+        let target = match monster_at(objects, x, y) {
+            Some(id) if game.fov.is_in_fov(x, y) => objects[id].name.clone(),
+            _ => "nothing".to_owned(),
+        };
+        let range_note = if in_range { "" } else { " (out of range)" };
+        tcod.root.set_default_foreground(WHITE);
+        tcod.root.print_ex(
+            1,
+            0,
+            BackgroundFlag::None,
+            TextAlignment::Left,
+            format!(
+                "Target: {}{}. Enter to confirm, Escape to cancel.",
+                target, range_note
+            ),
+        );
+        tcod.root.flush();
 
-        if !self.is_tile_blocked(objects, nx, ny) {
-            objects[id].move_by(x, y);
+        match input::check_for_event(input::KEY_PRESS | input::MOUSE) {
+            Some((_, input::Event::Mouse(mouse))) => {
+                let (cx, cy) = (mouse.cx as i32, mouse.cy as i32);
+                if cx >= 0 && cy >= 0 && cx < MAP_WIDTH && cy < MAP_HEIGHT {
+                    x = cx;
+                    y = cy;
+                }
+                if mouse.lbutton_pressed && game.in_target_range(objects, x, y, max_range) {
+                    return Some((x, y));
+                }
+                if mouse.rbutton_pressed {
+                    return None;
+                }
+            }
+            Some((_, input::Event::Key(key))) => match key.code {
+                Escape => return None,
+                Enter | NumPadEnter if in_range => return Some((x, y)),
+                _ => {
+                    let action = tcod.action_for(key);
+                    if let Some((dx, dy)) = action.and_then(Action::direction) {
+                        x = (x + dx).clamp(0, MAP_WIDTH - 1);
+                        y = (y + dy).clamp(0, MAP_HEIGHT - 1);
+                    }
+                }
+            },
+            None => {}
         }
     }
 
-    fn player_move_or_attack(&mut self, objects: &mut Vec<Object>, x: i32, y: i32) -> PlayerAction {
-        let nx = objects[PLAYER].x + x;
-        let ny = objects[PLAYER].y + y;
+    None
+}
 
-        let target_id = objects
-            .iter()
-            .position(|object| object.fighter.is_some() && object.pos() == (nx, ny));
+/// Asks for a direction with the movement keys. Any other key cancels.
+fn pick_direction(
+    tcod: &mut Tcod,
+    game: &Game,
+    objects: &[Object],
+    prompt: &str,
+) -> Option<(i32, i32)> {
+    while !tcod.root.window_closed() {
+        render_all(tcod, game, objects);
+        tcod.root.set_default_foreground(WHITE);
+        tcod.root
+            .print_ex(1, 0, BackgroundFlag::None, TextAlignment::Left, prompt);
+        tcod.root.flush();
 
-        match target_id {
-            Some(target_id) => {
-                let (player, monster) = mut_two(PLAYER, target_id, objects);
-                player.attack(monster, self);
-            }
-            None => {
-                self.move_player_by(objects, x, y);
+        match input::check_for_event(input::KEY_PRESS) {
+            // A printable key is followed by the text it types; wait for that.
+            Some((_, input::Event::Key(key)))
+                if key.code == input::KeyCode::Char && !key.ctrl => {}
+            Some((_, input::Event::Key(key))) => {
+                return tcod
+                    .action_for(key)
+                    .and_then(Action::direction)
+                    .filter(|&direction| direction != (0, 0))
             }
+            _ => {}
         }
-
-        PlayerAction::TookTurn
-    }
-
-    fn is_tile_blocked(&self, objects: &Vec<Object>, x: i32, y: i32) -> bool {
-        is_blocked(x, y, &self.map, objects)
     }
 
-    fn pick_item_up(&mut self, object_id: usize, objects: &mut Vec<Object>) {
-        if self.inventory.len() >= 26 {
-            self.messages.add(
-                format!(
-                    "Your inventory is full, cannot pick up {}.",
-                    objects[object_id].name
-                ),
-                RED,
-            )
-        } else {
-            let item = objects.swap_remove(object_id);
-            self.messages
-                .add(format!("You picked up a {}!", item.name), GREEN);
-            self.inventory.push(item);
-        }
-    }
+    None
 }
 
 fn main() {
@@ -144,25 +243,37 @@ fn main() {
 
     tcod::system::set_fps(LIMIT_FPS);
 
-    let mut player = Object::new(0, 0, '@', "player", WHITE, true);
-    player.alive = true;
-    player.fighter = Some(Fighter {
-        max_hp: 30,
-        hp: 30,
-        defense: 2,
-        power: 5,
-        on_death: DeathCallback::Player,
-    });
-
-    let mut objects = vec![player];
-
-    let mut game = Game {
-        map: make_map(&mut objects),
-        messages: Messages::new(),
-        inventory: vec![],
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
     };
 
-    game.messages.add("Welcome, gl hf!", RED);
+    let bindings = match KeyBindings::load() {
+        Ok(bindings) => bindings,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+
+    let templates = match Templates::load(TEMPLATES_FILE) {
+        Ok(Some(templates)) => templates,
+        Ok(None) => Templates::builtin(),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+
+    if let Some(depth) = args.spawn_table {
+        for line in describe_spawns(&templates, depth) {
+            println!("{}", line);
+        }
+        return;
+    }
 
     let con = Offscreen::new(MAP_WIDTH, MAP_HEIGHT);
     let panel = Offscreen::new(SCREEN_WIDTH, PANEL_HEIGHT);
@@ -175,298 +286,385 @@ fn main() {
         .init();
 
     let mut tcod = Tcod {
-        root: root,
-        fov: FovMap::new(MAP_WIDTH, MAP_HEIGHT),
-        con: con,
-        panel: panel,
+        root,
+        con,
+        panel,
         key: Default::default(),
+        bindings,
     };
 
-    tcod.populate_fov_map(MAP_WIDTH, MAP_HEIGHT, &mut game);
-
-    let mut previous_player_position = (-1, -1);
+    main_menu(&mut tcod, &args, &templates);
+}
 
+/// The title screen. Every game started from here returns to it when the
+/// player quits or dies.
+fn main_menu(tcod: &mut Tcod, args: &Args, templates: &Templates) {
     while !tcod.root.window_closed() {
-        tcod.con.clear();
-
-        let fov_recompute = previous_player_position != (objects[PLAYER].x, objects[PLAYER].y);
-
-        match input::check_for_event(input::KEY_PRESS) {
-            Some((_, Event::Key(k))) => tcod.key = k,
-            _ => tcod.key = Default::default(),
-        }
+        tcod.root.set_default_background(BLACK);
+        tcod.root.clear();
+        tcod.root.set_default_foreground(LIGHT_YELLOW);
+        tcod.root.print_ex(
+            SCREEN_WIDTH / 2,
+            SCREEN_HEIGHT / 2 - 6,
+            BackgroundFlag::None,
+            TextAlignment::Center,
+            "CRABLIKE",
+        );
+        tcod.root.print_ex(
+            SCREEN_WIDTH / 2,
+            SCREEN_HEIGHT - 2,
+            BackgroundFlag::None,
+            TextAlignment::Center,
+            "By Gonzih",
+        );
 
-        game.render_all(&mut tcod, &objects, fov_recompute);
+        let choices = &["New game", "Continue", "Quit"];
+        let choice = menu("", choices, MAIN_MENU_WIDTH, &mut tcod.root);
 
-        tcod.panel.set_default_background(BLACK);
-        tcod.panel.clear();
+        let (mut game, mut objects) = match choice {
+            // An explicit seed makes every new game the same dungeon.
+            Some(0) => {
+                let seed = args.seed.unwrap_or_else(rand::random);
+                new_game_with(seed, templates.clone())
+            }
+            Some(1) => match load_game() {
+                Ok(Some((mut game, objects))) => {
+                    game.templates = templates.clone();
+                    game.messages.add("Welcome back!", colors::RED);
+                    game.messages
+                        .add(format!("Dungeon seed: {}", game.seed), colors::WHITE);
+                    (game, objects)
+                }
+                Ok(None) => {
+                    msgbox("\nNo saved game to continue.\n", MAIN_MENU_WIDTH, &mut tcod.root);
+                    continue;
+                }
+                Err(e) => {
+                    let text = format!("\nCould not load saved game: {}\n", e);
+                    msgbox(&text, INVENTORY_WIDTH, &mut tcod.root);
+                    continue;
+                }
+            },
+            Some(2) => break,
+            _ => continue,
+        };
 
-        let hp = objects[PLAYER].fighter.map_or(0, |f| f.hp);
-        let max_hp = objects[PLAYER].fighter.map_or(0, |f| f.max_hp);
+        if let Some(history) = args.history {
+            game.messages.set_limit(history);
+        }
+        play_game(tcod, &mut game, &mut objects);
+    }
+}
 
-        tcod.render_bar(1, 1, BAR_WIDTH, "HP", hp, max_hp, LIGHT_RED, DARKER_RED);
+/// Runs a game until the player quits, which saves it, or dies, which
+/// shows the game-over screen and deletes the save.
+fn play_game(tcod: &mut Tcod, game: &mut Game, objects: &mut Vec<Object>) {
+    tcod.con.clear();
 
-        tcod.print_messages(&game.messages);
+    while !tcod.root.window_closed() {
+        match input::check_for_event(input::KEY_PRESS) {
+            Some((_, input::Event::Key(k))) => tcod.key = k,
+            _ => tcod.key = Default::default(),
+        }
 
-        tcod.blit_con(SCREEN_WIDTH, SCREEN_HEIGHT);
-        tcod.blit_panel(SCREEN_WIDTH, SCREEN_HEIGHT, PANEL_Y);
+        render_all(tcod, game, objects);
         tcod.root.flush();
 
-        let action = handle_keys(&mut tcod, &mut objects, &mut game);
+        let action = handle_keys(tcod, game, objects);
 
         match action {
             PlayerAction::Exit => break,
-            PlayerAction::TookTurn if objects[PLAYER].alive => {
-                for id in 0..objects.len() {
-                    if objects[id].ai.is_some() {
-                        ai_take_turn(id, &tcod, &mut objects, &mut game);
-                    }
+            PlayerAction::Play(command) => {
+                game.apply(command, objects);
+                while game.can_level_up(objects) {
+                    match level_up_menu(tcod, objects) {
+                        Some(choice) => game.apply(Command::LevelUp(choice), objects),
+                        None => break,
+                    };
+                }
+                if !objects[PLAYER].alive {
+                    render_all(tcod, game, objects);
+                    game_over_screen(tcod, game, objects);
+                    break;
                 }
             }
-            _ => {}
+            PlayerAction::DidntTakeTurn => {}
         }
     }
+
+    let saved = if objects[PLAYER].alive {
+        save_game(game, objects)
+    } else {
+        delete_save()
+    };
+    if let Err(e) = saved {
+        eprintln!("Could not save game: {}", e);
+    }
 }
 
-fn handle_keys(tcod: &mut Tcod, objects: &mut Vec<Object>, game: &mut Game) -> PlayerAction {
-    use tcod::input::KeyCode::*;
-    use PlayerAction::*;
+/// An effective stat next to its base value, like "8 (5 +3)".
+fn bonus(value: i32, base: i32) -> String {
+    if value == base {
+        format!("{}", value)
+    } else {
+        format!("{} ({} {:+})", value, base, value - base)
+    }
+}
 
-    let player_alive = objects[PLAYER].alive;
-    match (tcod.key, tcod.key.text(), player_alive) {
-        (Key { code: Up, .. }, _, true) => game.player_move_or_attack(objects, 0, -1),
-        (Key { code: Down, .. }, _, true) => game.player_move_or_attack(objects, 0, 1),
-        (Key { code: Left, .. }, _, true) => game.player_move_or_attack(objects, -1, 0),
-        (Key { code: Right, .. }, _, true) => game.player_move_or_attack(objects, 1, 0),
-        (Key { code: Escape, .. }, _, _) => Exit,
-        (
-            Key {
-                code: Enter,
-                alt: true,
-                ..
-            },
-            _,
-            _,
-        ) => {
-            let fullscreen = tcod.root.is_fullscreen();
-            tcod.root.set_fullscreen(!fullscreen);
-            DidntTakeTurn
+/// Everything about the player and the run so far, shared by the
+/// character sheet and the game-over screen.
+fn run_summary(game: &Game, objects: &[Object]) -> Vec<String> {
+    let player = &objects[PLAYER];
+    let stats = &game.stats;
+    let (hp, xp, base_max_hp, base_power, base_defense) = player
+        .fighter
+        .map_or((0, 0, 0, 0, 0), |f| (f.hp, f.xp, f.max_hp, f.power, f.defense));
+
+    let mut lines = vec![
+        format!("Character level: {}", player.level),
+        format!("Experience: {}/{}", xp, game.xp_to_level_up(objects)),
+        format!("HP: {}/{}", hp, bonus(player.max_hp(game), base_max_hp)),
+        format!("Attack: {}", bonus(player.power(game), base_power)),
+        format!("Defense: {}", bonus(player.defense(game), base_defense)),
+        format!("Speed: {}", player.fighter.map_or(0, |f| f.speed)),
+    ];
+
+    for item in &game.inventory {
+        if let Some(equipment) = item.equipment.filter(|e| e.equipped) {
+            lines.push(format!("  {} on {}", item.name, equipment.slot));
         }
+    }
 
-        (Key { code: Text, .. }, "g", true) => {
-            let item_id = objects
-                .iter()
-                .position(|object| object.pos() == objects[PLAYER].pos() && object.item.is_some());
-            if let Some(item_id) = item_id {
-                game.pick_item_up(item_id, objects);
-            };
-            TookTurn
-        }
+    lines.push(String::new());
+    lines.push(format!("Turns taken: {}", stats.turns));
+    lines.push(format!("Dungeon level: {}", game.dungeon_level));
+    lines.push(format!("Monsters killed: {}", stats.total_kills()));
+    for (name, count) in &stats.kills {
+        let name = if *count == 1 {
+            name.clone()
+        } else {
+            plural(name)
+        };
+        lines.push(format!("  {} {}", count, name));
+    }
 
-        (Key { code: Text, .. }, "i", true) => {
-            let inventory_index = inventory_menu(
-                &game.inventory,
-                "Press the key next an item to use it, or any other to cancel.\n",
-                &mut tcod.root,
-            );
-            if let Some(inventory_index) = inventory_index {
-                use_item(inventory_index, tcod, game, objects);
-            }
-            TookTurn
-        }
+    let effects = game.status_effects(objects);
+    if effects.is_empty() {
+        lines.push("Status: normal".into());
+    } else {
+        lines.push(format!("Status: {}", effects.join(", ")));
+    }
 
-        _ => DidntTakeTurn,
+    if let Some(cause) = &stats.cause_of_death {
+        lines.push(format!("Cause of death: {}", cause));
     }
+    lines
 }
 
-fn ai_take_turn(monster_id: usize, tcod: &Tcod, objects: &mut Vec<Object>, game: &mut Game) {
-    use Ai::*;
-    if let Some(ai) = objects[monster_id].ai.take() {
-        let new_ai = match ai {
-            Basic => ai_basic(monster_id, tcod, objects, game),
-            Confused {
-                previous_ai,
-                num_turns,
-            } => ai_confused(monster_id, tcod, objects, game, previous_ai, num_turns),
-        };
-        objects[monster_id].ai = Some(new_ai);
+fn character_sheet(tcod: &mut Tcod, game: &Game, objects: &[Object]) {
+    let mut text = "Character\n\n".to_owned();
+    for line in run_summary(game, objects) {
+        text.push_str(&line);
+        text.push('\n');
     }
+    text.push_str("\nPress any key to continue.");
+    msgbox(&text, INVENTORY_WIDTH, &mut tcod.root);
 }
 
-fn ai_basic(monster_id: usize, tcod: &Tcod, objects: &mut Vec<Object>, game: &mut Game) -> Ai {
-    let (monster_x, monster_y) = objects[monster_id].pos();
-
-    if tcod.fov.is_in_fov(monster_x, monster_y) {
-        if objects[monster_id].distance_to(&objects[PLAYER]) >= 2.0 {
-            let (player_x, player_y) = objects[PLAYER].pos();
-            move_towards(monster_id, player_x, player_y, &game.map, objects);
-        } else if objects[PLAYER].fighter.map_or(false, |f| f.hp > 0) {
-            let (player, monster) = mut_two(PLAYER, monster_id, objects);
-            monster.attack(player, game);
-        }
+fn game_over_screen(tcod: &mut Tcod, game: &Game, objects: &[Object]) {
+    let mut text = "You died!\n\n".to_owned();
+    for line in run_summary(game, objects) {
+        text.push_str(&line);
+        text.push('\n');
     }
-
-    Ai::Basic
+    text.push_str("\nPress any key to return to the title screen.");
+    msgbox(&text, INVENTORY_WIDTH, &mut tcod.root);
 }
 
-fn ai_confused(
-    monster_id: usize,
-    _tcod: &Tcod,
-    objects: &mut Vec<Object>,
-    game: &mut Game,
-    previous_ai: Box<Ai>,
-    num_turns: i32,
-) -> Ai {
-    if num_turns >= 0 {
-        game.move_object_by(
-            monster_id,
-            objects,
-            rand::thread_rng().gen_range(-1, 2),
-            rand::thread_rng().gen_range(-1, 2),
+/// Asks until a stat is picked, or returns `None` if the window is closed.
+fn level_up_menu(tcod: &mut Tcod, objects: &[Object]) -> Option<LevelUpChoice> {
+    let (max_hp, power, defense) = objects[PLAYER]
+        .fighter
+        .map_or((0, 0, 0), |f| (f.max_hp, f.power, f.defense));
+    let options = [
+        format!("Constitution (+{} HP, from {})", LEVEL_UP_HP, max_hp),
+        format!("Strength (+1 attack, from {})", power),
+        format!("Agility (+1 defense, from {})", defense),
+    ];
+
+    while !tcod.root.window_closed() {
+        let choice = menu(
+            "Level up! Choose a stat to raise:\n",
+            &options,
+            LEVEL_SCREEN_WIDTH,
+            &mut tcod.root,
         );
-        Ai::Confused {
-            previous_ai: previous_ai,
-            num_turns: num_turns - 1,
+        match choice {
+            Some(0) => return Some(LevelUpChoice::MaxHp),
+            Some(1) => return Some(LevelUpChoice::Power),
+            Some(2) => return Some(LevelUpChoice::Defense),
+            _ => {}
         }
-    } else {
-        game.messages.add(
-            format!("The {} is no longer confused!", objects[monster_id].name),
-            RED,
-        );
-
-        *previous_ai
     }
+    None
 }
 
-/// Mutably borrow two *separate* elements from the given slice.
-/// Panics when the indexes are equal or out of bounds.
-fn mut_two<T>(first_index: usize, second_index: usize, items: &mut [T]) -> (&mut T, &mut T) {
-    assert!(first_index != second_index);
-    let split_at_index = cmp::max(first_index, second_index);
-    let (first_slice, second_slice) = items.split_at_mut(split_at_index);
-    if first_index < second_index {
-        (&mut first_slice[first_index], &mut second_slice[0])
-    } else {
-        (&mut second_slice[0], &mut first_slice[second_index])
-    }
+/// Options given on the command line.
+#[derive(Default)]
+struct Args {
+    seed: Option<u64>,
+    history: Option<usize>,
+    /// Print the spawn odds for this dungeon level and exit.
+    spawn_table: Option<u32>,
 }
 
-enum UseResult {
-    UsedUp,
-    Cancelled,
-}
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args::default();
 
-fn use_item(inventory_id: usize, tcod: &mut Tcod, game: &mut Game, objects: &mut [Object]) {
-    use Item::*;
+    while let Some(arg) = args.next() {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name.to_owned(), Some(value.to_owned())),
+            None => (arg, None),
+        };
+        if name != "--seed" && name != "--history" && name != "--spawn-table" {
+            return Err(format!("Unknown argument: {}", name));
+        }
 
-    if let Some(item) = game.inventory[inventory_id].item {
-        let on_use = match item {
-            Heal => cast_heal,
-            Lightning => cast_lightning,
-            Confuse => cast_confuse,
+        let value = match value {
+            Some(value) => value,
+            None => args
+                .next()
+                .ok_or_else(|| format!("{} expects a number", name))?,
         };
-        match on_use(inventory_id, tcod, game, objects) {
-            UseResult::UsedUp => {
-                game.inventory.remove(inventory_id);
-            }
-            UseResult::Cancelled => {
-                game.messages.add("Cancelled", WHITE);
-            }
+
+        if name == "--seed" {
+            let seed = value
+                .parse()
+                .map_err(|_| format!("Invalid seed '{}', expected a number", value))?;
+            parsed.seed = Some(seed);
+        } else if name == "--spawn-table" {
+            let depth = value
+                .parse()
+                .ok()
+                .filter(|&depth| depth > 0)
+                .ok_or_else(|| format!("Invalid depth '{}', expected a level from 1 up", value))?;
+            parsed.spawn_table = Some(depth);
+        } else {
+            let history = value
+                .parse()
+                .map_err(|_| format!("Invalid history size '{}', expected a number", value))?;
+            parsed.history = Some(history);
         }
-    } else {
-        game.messages.add(
-            format!("The {} cannot be used.", game.inventory[inventory_id].name),
-            WHITE,
-        )
     }
+
+    Ok(parsed)
 }
 
-fn cast_heal(
-    _inventory_id: usize,
-    _tcod: &mut Tcod,
-    game: &mut Game,
-    objects: &mut [Object],
-) -> UseResult {
-    if let Some(fighter) = objects[PLAYER].fighter {
-        if fighter.hp == fighter.max_hp {
-            game.messages.add("You are alraedy at full health.", RED);
-            return UseResult::Cancelled;
+fn handle_keys(tcod: &mut Tcod, game: &Game, objects: &[Object]) -> PlayerAction {
+    use PlayerAction::*;
+
+    let player_alive = objects[PLAYER].alive;
+    let action = match tcod.action_for(tcod.key) {
+        Some(action) => action,
+        None => return DidntTakeTurn,
+    };
+
+    match (action, player_alive) {
+        (Action::Quit, _) => Exit,
+        (Action::ToggleFullscreen, _) => {
+            let fullscreen = tcod.root.is_fullscreen();
+            tcod.root.set_fullscreen(!fullscreen);
+            DidntTakeTurn
+        }
+        (Action::ShowBindings, _) => {
+            bindings_screen(&tcod.bindings, &mut tcod.root);
+            DidntTakeTurn
+        }
+        (Action::CharacterSheet, _) => {
+            character_sheet(tcod, game, objects);
+            DidntTakeTurn
+        }
+        (Action::MessageLog, _) => {
+            message_log(&game.messages, &mut tcod.root);
+            DidntTakeTurn
         }
 
-        game.messages.add("Your wounds are closing!", LIGHT_VIOLET);
-        objects[PLAYER].heal(HEAL_AMOUNT);
-        return UseResult::UsedUp;
-    }
+        (Action::Wait, true) => Play(Command::Wait),
 
-    UseResult::Cancelled
-}
+        (Action::PickUp, true) => Play(Command::PickUp),
 
-fn cast_lightning(
-    _inventory_id: usize,
-    tcod: &mut Tcod,
-    game: &mut Game,
-    objects: &mut [Object],
-) -> UseResult {
-    let monster_id = closest_monster(tcod, objects, LIGHTNING_RANGE);
-    if let Some(monster_id) = monster_id {
-        game.messages.add(
-            format!(
-                "A lightting bolt strikes the {} with a loud thunder! for {} hit points.",
-                objects[monster_id].name, LIGHTNING_DAMAGE,
-            ),
-            LIGHT_BLUE,
-        );
-        objects[monster_id].take_damage(LIGHTNING_DAMAGE, game);
-        UseResult::UsedUp
-    } else {
-        game.messages
-            .add("No enemy is close enough to strike.", RED);
-        UseResult::Cancelled
-    }
-}
+        (Action::Descend, true) => Play(Command::Descend),
 
-fn closest_monster(tcod: &Tcod, objects: &[Object], max_range: i32) -> Option<usize> {
-    let mut closest_enemy = None;
-    let mut closest_dist = (max_range + 1) as f32;
-
-    for (id, object) in objects.iter().enumerate() {
-        if (id != PLAYER)
-            && object.fighter.is_some()
-            && object.ai.is_some()
-            && tcod.fov.is_in_fov(object.x, object.y)
-        {
-            let dist = objects[PLAYER].distance_to(object);
-            if dist < closest_dist {
-                closest_enemy = Some(id);
-                closest_dist = dist;
+        (Action::Inventory, true) => {
+            let inventory_index = inventory_menu(
+                &game.inventory,
+                "Press the key next an item to use or (un)equip it, or any other to cancel.\n",
+                &mut tcod.root,
+            );
+            match inventory_index {
+                Some(inventory_index) => match game.inventory[inventory_index].equipment {
+                    Some(equipment) if equipment.equipped => {
+                        Play(Command::Unequip(inventory_index))
+                    }
+                    Some(_) => Play(Command::Equip(inventory_index)),
+                    None => {
+                        let item = game.inventory[inventory_index].item;
+                        let target = match item.and_then(item_target_range) {
+                            Some(range) => {
+                                let radius = item.map_or(0, item_blast_radius);
+                                target_tile(tcod, game, objects, range, radius)
+                            }
+                            None => None,
+                        };
+                        Play(Command::UseItem(inventory_index, target))
+                    }
+                },
+                None => DidntTakeTurn,
             }
         }
-    }
 
-    closest_enemy
-}
+        (Action::Drop, true) => {
+            let inventory_index = inventory_menu(
+                &game.inventory,
+                "Press the key next to an item to drop it, or any other to cancel.\n",
+                &mut tcod.root,
+            );
+            match inventory_index {
+                Some(inventory_index) => Play(Command::Drop(inventory_index)),
+                None => DidntTakeTurn,
+            }
+        }
 
-fn cast_confuse(
-    _inventory_id: usize,
-    tcod: &mut Tcod,
-    game: &mut Game,
-    objects: &mut [Object],
-) -> UseResult {
-    let monster_id = closest_monster(tcod, objects, CONFUSE_RANGE);
-    if let Some(monster_id) = monster_id {
-        game.messages.add(
-            format!("{} looks confused", objects[monster_id].name,),
-            LIGHT_GREEN,
-        );
-        let old_ai = objects[monster_id].ai.take().unwrap_or(Ai::Basic);
-        objects[monster_id].ai = Some(Ai::Confused {
-            previous_ai: Box::new(old_ai),
-            num_turns: CONFUSE_NUM_TURNS,
-        });
-        UseResult::UsedUp
-    } else {
-        game.messages
-            .add("No enemy is close enough to strike.", RED);
-        UseResult::Cancelled
+        (Action::CloseDoor, true) => {
+            // Only ask which door when there is a choice.
+            let (px, py) = objects[PLAYER].pos();
+            let doors: Vec<_> = (-1..=1)
+                .flat_map(|dx| (-1..=1).map(move |dy| (dx, dy)))
+                .filter(|&(dx, dy)| {
+                    let (x, y) = (px + dx, py + dy);
+                    (dx, dy) != (0, 0)
+                        && x >= 0
+                        && y >= 0
+                        && x < MAP_WIDTH
+                        && y < MAP_HEIGHT
+                        && game.map[x as usize][y as usize].is_open_door()
+                })
+                .collect();
+            let direction = match doors[..] {
+                [door] => Some(door),
+                _ => pick_direction(tcod, game, objects, "Close the door in which direction?"),
+            };
+            match direction {
+                Some((dx, dy)) => Play(Command::CloseDoor { dx, dy }),
+                None => DidntTakeTurn,
+            }
+        }
+
+        (action, true) => match action.direction() {
+            Some((dx, dy)) => Play(Command::Move { dx, dy }),
+            None => DidntTakeTurn,
+        },
+
+        (_, false) => DidntTakeTurn,
     }
 }
@@ -0,0 +1,481 @@
+use super::gamemap::{Map, Rect, Tile, MAP_HEIGHT, MAP_WIDTH};
+use rand::Rng;
+use std::cmp;
+use std::collections::{BTreeMap, VecDeque};
+use std::ops::Range;
+
+const ROOM_MAX_SIZE: i32 = 10;
+const ROOM_MIN_SIZE: i32 = 6;
+const MAX_ROOMS: i32 = 30;
+
+/// BSP leaves are never cut smaller than this, so every leaf fits a room.
+const BSP_MIN_LEAF: i32 = ROOM_MIN_SIZE + 2;
+/// Leaves up to this size may stop splitting early, for some variety in
+/// room density.
+const BSP_MAX_LEAF: i32 = 16;
+
+/// Chance that a tile starts out as wall before the cave is smoothed.
+const CAVE_WALL_CHANCE: f64 = 0.45;
+const CAVE_SMOOTHING_STEPS: u32 = 5;
+/// Caves whose largest open area is smaller than this are thrown away.
+const CAVE_MIN_FLOOR: usize = (MAP_WIDTH * MAP_HEIGHT / 3) as usize;
+/// Caves are cut into a grid of this many columns and rows to spread
+/// monsters and items. Each cell gets the spawns of one room, so the grid
+/// has about as many cells as a `Rooms` level has rooms.
+const CAVE_REGION_COLUMNS: i32 = 4;
+const CAVE_REGION_ROWS: i32 = 3;
+/// Cells with less cave floor than this get nothing placed in them.
+const CAVE_MIN_REGION: usize = 20;
+
+/// The algorithms that can lay out a level. All of them produce a `Layout`,
+/// so the rest of level creation does not care which one was used.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Generator {
+    /// Rooms thrown at random spots, dropping any that overlap.
+    #[default]
+    Rooms,
+    /// Binary space partitioning: the map is split into nested halves with
+    /// one room in each leaf, and sibling halves are joined by a tunnel.
+    Bsp,
+    /// Cellular automata caves, trimmed down to their largest open area.
+    Cave,
+}
+
+/// A freshly carved level before anything is placed in it. The player
+/// starts in the first region and the stairs go in the last one.
+pub struct Layout {
+    pub map: Map,
+    pub regions: Vec<Region>,
+}
+
+/// Part of a level that gets its own share of monsters and items: a room,
+/// or a patch of cave.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Region {
+    /// Floor tiles that things may be placed on.
+    pub tiles: Vec<(i32, i32)>,
+    /// A floor tile near the middle, for the player or the stairs.
+    pub center: (i32, i32),
+}
+
+impl From<&Rect> for Region {
+    fn from(room: &Rect) -> Self {
+        let tiles = ((room.x1 + 1)..room.x2)
+            .flat_map(|x| ((room.y1 + 1)..room.y2).map(move |y| (x, y)))
+            .collect();
+        Region {
+            tiles,
+            center: room.center(),
+        }
+    }
+}
+
+impl Generator {
+    pub const ALL: [Generator; 3] = [Generator::Rooms, Generator::Bsp, Generator::Cave];
+
+    /// The name used for this generator in data files.
+    pub fn name(self) -> &'static str {
+        match self {
+            Generator::Rooms => "rooms",
+            Generator::Bsp => "bsp",
+            Generator::Cave => "cave",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Generator> {
+        Generator::ALL.iter().copied().find(|g| g.name() == name)
+    }
+
+    pub fn generate(self, rng: &mut impl Rng) -> Layout {
+        match self {
+            Generator::Rooms => random_rooms(rng),
+            Generator::Bsp => bsp(rng),
+            Generator::Cave => cave(rng),
+        }
+    }
+}
+
+fn random_rooms(rng: &mut impl Rng) -> Layout {
+    let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+    let mut rooms: Vec<Rect> = vec![];
+
+    for _ in 0..MAX_ROOMS {
+        let w = rng.gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1);
+        let h = rng.gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1);
+        let x = rng.gen_range(0, MAP_WIDTH - w);
+        let y = rng.gen_range(0, MAP_HEIGHT - h);
+
+        let room = Rect::new(x, y, w, h);
+        let failed = rooms
+            .iter()
+            .any(|other_room| room.intersects_with(other_room));
+
+        if !failed {
+            create_room(&room, &mut map);
+
+            if let Some(prev_room) = rooms.last() {
+                connect(prev_room, &room, &mut map, rng);
+            }
+
+            rooms.push(room);
+        }
+    }
+
+    add_doors(&rooms, &mut map);
+    let regions = rooms.iter().map(Region::from).collect();
+    Layout { map, regions }
+}
+
+fn bsp(rng: &mut impl Rng) -> Layout {
+    let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+    let mut rooms = vec![];
+
+    let whole_map = Rect::new(0, 0, MAP_WIDTH - 1, MAP_HEIGHT - 1);
+    bsp_split(whole_map, &mut map, &mut rooms, rng);
+
+    add_doors(&rooms, &mut map);
+    let regions = rooms.iter().map(Region::from).collect();
+    Layout { map, regions }
+}
+
+/// Splits `leaf` in two and recurses, or carves a room in it once it is too
+/// small to split. Returns the indices in `rooms` of the rooms carved
+/// inside `leaf`, which are always connected to each other.
+fn bsp_split(leaf: Rect, map: &mut Map, rooms: &mut Vec<Rect>, rng: &mut impl Rng) -> Range<usize> {
+    let w = leaf.x2 - leaf.x1;
+    let h = leaf.y2 - leaf.y1;
+    let can_split_x = w >= 2 * BSP_MIN_LEAF;
+    let can_split_y = h >= 2 * BSP_MIN_LEAF;
+    let small = w <= BSP_MAX_LEAF && h <= BSP_MAX_LEAF;
+
+    let split_x = match (can_split_x, can_split_y) {
+        (false, false) => None,
+        _ if small && rng.gen_ratio(1, 4) => None,
+        (true, false) => Some(true),
+        (false, true) => Some(false),
+        // Cut across the longer side so leaves stay roughly square.
+        (true, true) if w * 4 > h * 5 => Some(true),
+        (true, true) if h * 4 > w * 5 => Some(false),
+        (true, true) => Some(rng.gen()),
+    };
+
+    let (first, second) = match split_x {
+        Some(true) => {
+            let at = leaf.x1 + rng.gen_range(BSP_MIN_LEAF, w - BSP_MIN_LEAF + 1);
+            (Rect { x2: at, ..leaf }, Rect { x1: at, ..leaf })
+        }
+        Some(false) => {
+            let at = leaf.y1 + rng.gen_range(BSP_MIN_LEAF, h - BSP_MIN_LEAF + 1);
+            (Rect { y2: at, ..leaf }, Rect { y1: at, ..leaf })
+        }
+        None => {
+            let room_w = rng.gen_range(ROOM_MIN_SIZE, cmp::min(ROOM_MAX_SIZE, w) + 1);
+            let room_h = rng.gen_range(ROOM_MIN_SIZE, cmp::min(ROOM_MAX_SIZE, h) + 1);
+            let x = rng.gen_range(leaf.x1, leaf.x2 - room_w + 1);
+            let y = rng.gen_range(leaf.y1, leaf.y2 - room_h + 1);
+
+            let room = Rect::new(x, y, room_w, room_h);
+            create_room(&room, map);
+            rooms.push(room);
+            return rooms.len() - 1..rooms.len();
+        }
+    };
+
+    let first = bsp_split(first, map, rooms, rng);
+    let second = bsp_split(second, map, rooms, rng);
+
+    // Join the two halves through their closest pair of rooms.
+    let distance = |a: usize, b: usize| {
+        let (ax, ay) = rooms[a].center();
+        let (bx, by) = rooms[b].center();
+        (ax - bx).abs() + (ay - by).abs()
+    };
+    let (a, b) = first
+        .clone()
+        .flat_map(|a| second.clone().map(move |b| (a, b)))
+        .min_by_key(|&(a, b)| distance(a, b))
+        .expect("every leaf has a room");
+    let (room_a, room_b) = (rooms[a], rooms[b]);
+    connect(&room_a, &room_b, map, rng);
+
+    first.start..second.end
+}
+
+fn cave(rng: &mut impl Rng) -> Layout {
+    loop {
+        let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+        for x in 1..MAP_WIDTH - 1 {
+            for y in 1..MAP_HEIGHT - 1 {
+                if !rng.gen_bool(CAVE_WALL_CHANCE) {
+                    map[x as usize][y as usize] = Tile::empty();
+                }
+            }
+        }
+        for _ in 0..CAVE_SMOOTHING_STEPS {
+            map = smooth_cave(&map);
+        }
+
+        // Fill in every pocket that is cut off from the main cave.
+        let floor = largest_area(&map);
+        if floor.len() < CAVE_MIN_FLOOR {
+            continue;
+        }
+        let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+        for &(x, y) in &floor {
+            map[x as usize][y as usize] = Tile::empty();
+        }
+
+        let regions = cave_regions(&floor);
+        if regions.len() >= 2 {
+            return Layout { map, regions };
+        }
+    }
+}
+
+/// One cellular automata step: a tile becomes wall when most of the 3x3
+/// square around it is wall, and floor otherwise. The map edge stays wall.
+fn smooth_cave(map: &Map) -> Map {
+    let mut next = map.clone();
+    for x in 1..MAP_WIDTH - 1 {
+        for y in 1..MAP_HEIGHT - 1 {
+            let mut walls = 0;
+            for nx in x - 1..=x + 1 {
+                for ny in y - 1..=y + 1 {
+                    if map[nx as usize][ny as usize].blocked {
+                        walls += 1;
+                    }
+                }
+            }
+            next[x as usize][y as usize] = if walls >= 5 {
+                Tile::wall()
+            } else {
+                Tile::empty()
+            };
+        }
+    }
+    next
+}
+
+/// The biggest set of floor tiles that are all reachable from each other.
+fn largest_area(map: &Map) -> Vec<(i32, i32)> {
+    let mut seen = vec![vec![false; MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+    let mut largest = vec![];
+
+    for x in 0..MAP_WIDTH {
+        for y in 0..MAP_HEIGHT {
+            if !map[x as usize][y as usize].is_walkable() || seen[x as usize][y as usize] {
+                continue;
+            }
+            let area = fill(map, (x, y), &mut seen);
+            if area.len() > largest.len() {
+                largest = area;
+            }
+        }
+    }
+
+    largest
+}
+
+/// Every floor or door tile reachable from `start`, moving the way the
+/// player does: one step in any of the eight directions, through doors but
+/// never through walls.
+pub fn flood_fill(map: &Map, start: (i32, i32)) -> Vec<(i32, i32)> {
+    let mut seen = vec![vec![false; MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+    fill(map, start, &mut seen)
+}
+
+/// `flood_fill` that skips and marks tiles in `seen`.
+fn fill(map: &Map, start: (i32, i32), seen: &mut [Vec<bool>]) -> Vec<(i32, i32)> {
+    let mut found = vec![];
+    let mut todo = vec![start];
+
+    while let Some((x, y)) = todo.pop() {
+        if x < 0 || y < 0 || x >= MAP_WIDTH || y >= MAP_HEIGHT {
+            continue;
+        }
+        if seen[x as usize][y as usize] || !map[x as usize][y as usize].is_walkable() {
+            continue;
+        }
+        seen[x as usize][y as usize] = true;
+        found.push((x, y));
+
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                todo.push((x + dx, y + dy));
+            }
+        }
+    }
+
+    found
+}
+
+/// The floor tile the longest walk away from `start`, or `start` itself if
+/// no other floor can be reached. Doors are walked through but never
+/// picked.
+pub fn farthest_floor(map: &Map, start: (i32, i32)) -> (i32, i32) {
+    let mut seen = vec![vec![false; MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+    seen[start.0 as usize][start.1 as usize] = true;
+    let mut todo = VecDeque::new();
+    todo.push_back(start);
+
+    let mut farthest = start;
+    while let Some((x, y)) = todo.pop_front() {
+        if !map[x as usize][y as usize].door {
+            farthest = (x, y);
+        }
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                let (nx, ny) = (x + dx, y + dy);
+                if nx < 0 || ny < 0 || nx >= MAP_WIDTH || ny >= MAP_HEIGHT {
+                    continue;
+                }
+                if seen[nx as usize][ny as usize] || !map[nx as usize][ny as usize].is_walkable() {
+                    continue;
+                }
+                seen[nx as usize][ny as usize] = true;
+                todo.push_back((nx, ny));
+            }
+        }
+    }
+
+    farthest
+}
+
+/// Tunnels every patch of floor that cannot be reached from `start` to
+/// the nearest floor that can, by the shortest way through rock.
+pub fn connect_unreachable(map: &mut Map, start: (i32, i32)) {
+    loop {
+        let mut came_from = vec![vec![None; MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+        let mut todo = VecDeque::new();
+        for (x, y) in flood_fill(map, start) {
+            came_from[x as usize][y as usize] = Some((x, y));
+            todo.push_back((x, y));
+        }
+
+        let mut cut_off = None;
+        'search: while let Some((x, y)) = todo.pop_front() {
+            for &(dx, dy) in &[(-1, 0), (1, 0), (0, -1), (0, 1)] {
+                let (nx, ny) = (x + dx, y + dy);
+                if nx < 1 || ny < 1 || nx >= MAP_WIDTH - 1 || ny >= MAP_HEIGHT - 1 {
+                    continue;
+                }
+                if came_from[nx as usize][ny as usize].is_some() {
+                    continue;
+                }
+                came_from[nx as usize][ny as usize] = Some((x, y));
+                if map[nx as usize][ny as usize].is_walkable() {
+                    cut_off = Some((nx, ny));
+                    break 'search;
+                }
+                todo.push_back((nx, ny));
+            }
+        }
+
+        // Walk back from the cut off floor, digging out the rock between.
+        let mut step = match cut_off {
+            Some(tile) => tile,
+            None => return,
+        };
+        while let Some(prev) = came_from[step.0 as usize][step.1 as usize] {
+            if prev == step {
+                break;
+            }
+            map[step.0 as usize][step.1 as usize] = Tile::empty();
+            step = prev;
+        }
+    }
+}
+
+/// Cuts a cave along the `CAVE_REGION_COLUMNS` by `CAVE_REGION_ROWS` grid,
+/// from left to right so the player start and the stairs end up far apart.
+fn cave_regions(floor: &[(i32, i32)]) -> Vec<Region> {
+    let cell_width = (MAP_WIDTH + CAVE_REGION_COLUMNS - 1) / CAVE_REGION_COLUMNS;
+    let cell_height = (MAP_HEIGHT + CAVE_REGION_ROWS - 1) / CAVE_REGION_ROWS;
+
+    let mut cells: BTreeMap<(i32, i32), Vec<(i32, i32)>> = BTreeMap::new();
+    for &(x, y) in floor {
+        cells
+            .entry((x / cell_width, y / cell_height))
+            .or_default()
+            .push((x, y));
+    }
+
+    cells
+        .into_values()
+        .filter(|tiles| tiles.len() >= CAVE_MIN_REGION)
+        .map(|tiles| {
+            let count = tiles.len() as i32;
+            let mid_x = tiles.iter().map(|&(x, _)| x).sum::<i32>() / count;
+            let mid_y = tiles.iter().map(|&(_, y)| y).sum::<i32>() / count;
+            let center = *tiles
+                .iter()
+                .min_by_key(|&&(x, y)| (x - mid_x).pow(2) + (y - mid_y).pow(2))
+                .expect("regions are never empty");
+            Region { tiles, center }
+        })
+        .collect()
+}
+
+/// Joins the centers of two rooms with an L-shaped tunnel, turning the
+/// corner at random.
+fn connect(from: &Rect, to: &Rect, map: &mut Map, rng: &mut impl Rng) {
+    let (prev_x, prev_y) = from.center();
+    let (new_x, new_y) = to.center();
+
+    if rng.gen() {
+        create_h_tunnel(prev_x, new_x, prev_y, map);
+        create_v_tunnel(prev_y, new_y, new_x, map);
+    } else {
+        create_v_tunnel(prev_y, new_y, prev_x, map);
+        create_h_tunnel(prev_x, new_x, new_y, map);
+    }
+}
+
+/// Puts a closed door in every one tile wide gap that a tunnel cut through
+/// the wall of a room.
+fn add_doors(rooms: &[Rect], map: &mut Map) {
+    let is_floor = |map: &Map, x: i32, y: i32| {
+        x >= 0 && y >= 0 && x < MAP_WIDTH && y < MAP_HEIGHT && !map[x as usize][y as usize].blocked
+    };
+
+    for room in rooms {
+        // Each wall tile with the direction the wall runs in, corners left out.
+        let horizontal = ((room.x1 + 1)..room.x2)
+            .flat_map(|x| vec![(x, room.y1), (x, room.y2)])
+            .map(|tile| (tile, (1, 0)));
+        let vertical = ((room.y1 + 1)..room.y2)
+            .flat_map(|y| vec![(room.x1, y), (room.x2, y)])
+            .map(|tile| (tile, (0, 1)));
+
+        for ((x, y), (ax, ay)) in horizontal.chain(vertical) {
+            let gap = is_floor(map, x, y)
+                && !is_floor(map, x - ax, y - ay)
+                && !is_floor(map, x + ax, y + ay)
+                && is_floor(map, x - ay, y - ax)
+                && is_floor(map, x + ay, y + ax);
+            if gap {
+                map[x as usize][y as usize] = Tile::door();
+            }
+        }
+    }
+}
+
+fn create_room(room: &Rect, map: &mut Map) {
+    for x in (room.x1 + 1)..room.x2 {
+        for y in (room.y1 + 1)..room.y2 {
+            map[x as usize][y as usize] = Tile::empty();
+        }
+    }
+}
+
+fn create_h_tunnel(x1: i32, x2: i32, y: i32, map: &mut Map) {
+    for x in cmp::min(x1, x2)..(cmp::max(x1, x2) + 1) {
+        map[x as usize][y as usize] = Tile::empty();
+    }
+}
+
+fn create_v_tunnel(y1: i32, y2: i32, x: i32, map: &mut Map) {
+    for y in cmp::min(y1, y2)..(cmp::max(y1, y2) + 1) {
+        map[x as usize][y as usize] = Tile::empty();
+    }
+}
@@ -0,0 +1,76 @@
+use super::colors::Color;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// How many messages are kept unless the front end asks for another limit.
+pub const DEFAULT_HISTORY: usize = 1000;
+
+#[derive(Serialize, Deserialize)]
+pub struct Messages {
+    messages: VecDeque<(String, Color)>,
+    limit: usize,
+    /// How many times in a row the newest message was added.
+    repeats: u32,
+}
+
+impl Messages {
+    pub fn new() -> Self {
+        Self {
+            messages: VecDeque::new(),
+            limit: DEFAULT_HISTORY,
+            repeats: 0,
+        }
+    }
+
+    /// Adds a message, dropping the oldest ones past the history limit. The
+    /// same message added again right away is shown once as "message (x3)".
+    pub fn add<T: Into<String>>(&mut self, message: T, color: Color) {
+        let message = message.into();
+
+        if self.is_repeat(&message, color) {
+            self.repeats += 1;
+            if let Some(last) = self.messages.back_mut() {
+                last.0 = format!("{} (x{})", message, self.repeats);
+            }
+        } else {
+            self.repeats = 1;
+            self.messages.push_back((message, color));
+            self.trim();
+        }
+    }
+
+    /// Keeps at most `limit` messages, forgetting the oldest first.
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit.max(1);
+        self.trim();
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &(String, Color)> {
+        self.messages.iter()
+    }
+
+    fn is_repeat(&self, message: &str, color: Color) -> bool {
+        match self.messages.back() {
+            Some((last, last_color)) if *last_color == color => {
+                if self.repeats > 1 {
+                    last.strip_suffix(&format!(" (x{})", self.repeats)) == Some(message)
+                } else {
+                    last == message
+                }
+            }
+            _ => false,
+        }
+    }
+
+    fn trim(&mut self) {
+        while self.messages.len() > self.limit {
+            self.messages.pop_front();
+        }
+    }
+}
+
+impl Default for Messages {
+    fn default() -> Self {
+        Self::new()
+    }
+}
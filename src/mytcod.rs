@@ -1,8 +1,11 @@
-use super::object::{Object};
+use crablike::colors;
+use crablike::messages::Messages;
+use crablike::object::Object;
+use crablike::keybindings::{Action, KeyBindings, KeyName, KeySpec, NamedKey};
 use tcod::colors::*;
 use tcod::console::*;
-use tcod::map::{FovAlgorithm, Map as FovMap};
-use tcod::input::{Key};
+use std::cmp;
+use tcod::input::{Key, KeyCode};
 
 pub const SCREEN_WIDTH: i32 = 80;
 pub const SCREEN_HEIGHT: i32 = 50;
@@ -12,57 +15,106 @@ pub const PANEL_HEIGHT: i32 = 7;
 pub const PANEL_Y: i32 = SCREEN_HEIGHT - PANEL_HEIGHT;
 
 pub const INVENTORY_WIDTH: i32 = 50;
+pub const MENU_PAGE_SIZE: i32 = 26;
+pub const LOG_WIDTH: i32 = SCREEN_WIDTH - 2;
+pub const LEVEL_SCREEN_WIDTH: i32 = 40;
+pub const MAIN_MENU_WIDTH: i32 = 24;
+pub const BINDINGS_SCREEN_WIDTH: i32 = 60;
 
-const FOV_ALGO: FovAlgorithm = FovAlgorithm::Basic;
-const FOV_LIGHT_WALLS: bool = true;
-pub const TORCH_RADIUS: i32 = 10;
 pub const MSG_X: i32 = BAR_WIDTH + 2;
 pub const MSG_WIDTH: i32 = SCREEN_WIDTH - BAR_WIDTH - 2;
 pub const MSG_HEIGHT: usize = PANEL_HEIGHT as usize - 1;
 
-pub struct Messages {
-    messages: Vec<(String, Color)>,
+pub fn tcod_color(color: colors::Color) -> Color {
+    Color::new(color.r, color.g, color.b)
 }
 
-impl Messages {
-    pub fn new() -> Self {
-        Self { messages: vec![] }
-    }
+pub fn draw_object(object: &Object, con: &mut dyn Console) {
+    con.set_default_foreground(tcod_color(object.color));
+    con.put_char(object.x, object.y, object.char, BackgroundFlag::None);
+}
 
-    pub fn add<T: Into<String>>(&mut self, message: T, color: Color) {
-        self.messages.push((message.into(), color));
-    }
+/// How tcod names the keys bindings can use by name.
+const TCOD_KEYS: [(KeyCode, NamedKey); 37] = [
+    (KeyCode::Escape, NamedKey::Escape),
+    (KeyCode::Enter, NamedKey::Enter),
+    (KeyCode::Tab, NamedKey::Tab),
+    (KeyCode::Backspace, NamedKey::Backspace),
+    (KeyCode::Spacebar, NamedKey::Space),
+    (KeyCode::Insert, NamedKey::Insert),
+    (KeyCode::Delete, NamedKey::Delete),
+    (KeyCode::Home, NamedKey::Home),
+    (KeyCode::End, NamedKey::End),
+    (KeyCode::PageUp, NamedKey::PageUp),
+    (KeyCode::PageDown, NamedKey::PageDown),
+    (KeyCode::Up, NamedKey::Up),
+    (KeyCode::Down, NamedKey::Down),
+    (KeyCode::Left, NamedKey::Left),
+    (KeyCode::Right, NamedKey::Right),
+    (KeyCode::NumPad0, NamedKey::NumPad0),
+    (KeyCode::NumPad1, NamedKey::NumPad1),
+    (KeyCode::NumPad2, NamedKey::NumPad2),
+    (KeyCode::NumPad3, NamedKey::NumPad3),
+    (KeyCode::NumPad4, NamedKey::NumPad4),
+    (KeyCode::NumPad5, NamedKey::NumPad5),
+    (KeyCode::NumPad6, NamedKey::NumPad6),
+    (KeyCode::NumPad7, NamedKey::NumPad7),
+    (KeyCode::NumPad8, NamedKey::NumPad8),
+    (KeyCode::NumPad9, NamedKey::NumPad9),
+    (KeyCode::NumPadEnter, NamedKey::NumPadEnter),
+    (KeyCode::F1, NamedKey::F1),
+    (KeyCode::F2, NamedKey::F2),
+    (KeyCode::F3, NamedKey::F3),
+    (KeyCode::F4, NamedKey::F4),
+    (KeyCode::F5, NamedKey::F5),
+    (KeyCode::F6, NamedKey::F6),
+    (KeyCode::F7, NamedKey::F7),
+    (KeyCode::F8, NamedKey::F8),
+    (KeyCode::F9, NamedKey::F9),
+    (KeyCode::F10, NamedKey::F10),
+    (KeyCode::F11, NamedKey::F11),
+];
 
-    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &(String, Color)> {
-        self.messages.iter()
-    }
+/// A stat drawn by `Tcod::render_bar`, filled in proportion to its maximum.
+pub struct Bar<'a> {
+    pub name: &'a str,
+    pub value: i32,
+    pub maximum: i32,
+    pub bar_color: Color,
+    pub back_color: Color,
 }
 
 pub struct Tcod {
     pub root: Root,
-    pub fov: FovMap,
     pub con: Offscreen,
     pub panel: Offscreen,
     pub key: Key,
+    pub bindings: KeyBindings,
 }
 
 impl Tcod {
-    pub fn compute_fov(&mut self, x: i32, y: i32) {
-        self.fov
-            .compute_fov(x, y, TORCH_RADIUS, FOV_LIGHT_WALLS, FOV_ALGO);
-    }
-
-    pub fn populate_fov_map(&mut self, w: i32, h: i32, game: &mut super::Game) {
-        for y in 0..h {
-            for x in 0..w {
-                &self.fov.set(
-                    x,
-                    y,
-                    !game.map[x as usize][y as usize].block_sight,
-                    !game.map[x as usize][y as usize].blocked,
-                );
+    /// The action bound to a key press, if any.
+    ///
+    /// A printable key normally arrives twice, as a `Char` key and then as
+    /// the `Text` it types, and only the `Text` is used. Holding Ctrl types
+    /// nothing, so Ctrl+char bindings are matched on the `Char` key instead.
+    pub fn action_for(&self, key: Key) -> Option<Action> {
+        let name = if key.code == KeyCode::Text {
+            KeyName::Text(key.text().to_owned())
+        } else if key.code == KeyCode::Char {
+            if !key.ctrl {
+                return None;
             }
-        }
+            KeyName::Text(key.printable.to_string())
+        } else {
+            let (_, named) = TCOD_KEYS.iter().find(|(code, _)| *code == key.code)?;
+            KeyName::Named(*named)
+        };
+        self.bindings.action_for(&KeySpec {
+            key: name,
+            alt: key.alt,
+            ctrl: key.ctrl,
+        })
     }
 
     pub fn blit_con(&mut self, width: i32, height: i32) {
@@ -89,17 +141,14 @@ impl Tcod {
         );
     }
 
-    pub fn render_bar(
-        &mut self,
-        x: i32,
-        y: i32,
-        total_width: i32,
-        name: &str,
-        value: i32,
-        maximum: i32,
-        bar_color: Color,
-        back_color: Color,
-    ) {
+    pub fn render_bar(&mut self, x: i32, y: i32, total_width: i32, bar: Bar) {
+        let Bar {
+            name,
+            value,
+            maximum,
+            bar_color,
+            back_color,
+        } = bar;
         let bar_width = (value as f32 / maximum as f32 * total_width as f32) as i32;
 
         self.panel.set_default_background(back_color);
@@ -118,7 +167,7 @@ impl Tcod {
             y,
             BackgroundFlag::None,
             TextAlignment::Center,
-            &format!("{}: {}/{}", name, value, maximum),
+            format!("{}: {}/{}", name, value, maximum),
         );
     }
 
@@ -131,75 +180,301 @@ impl Tcod {
             if y < 0 {
                 break;
             }
-            self.panel.set_default_foreground(color);
+            self.panel.set_default_foreground(tcod_color(color));
             self.panel.print_rect(MSG_X, y, MSG_WIDTH, 0, msg);
         }
     }
 }
 
-fn menu<T: AsRef<str>>(header: &str, options: &[T], width: i32, root: &mut Root) -> Option<usize> {
-    assert!(
-        options.len() <= 26,
-        "Cannot have a menu with more than 26 options."
-    );
+/// A menu scrolls once it has more options than fit on screen at once, or
+/// than there are letters to pick them with.
+fn menu_page_size(header_height: i32) -> usize {
+    (SCREEN_HEIGHT - header_height - 1).clamp(1, MENU_PAGE_SIZE) as usize
+}
+
+/// Shows `options` under `header` and waits for a choice. Letters pick one
+/// of the options on screen, the arrow keys move the highlight and scroll,
+/// PageUp and PageDown jump a page, and Enter picks the highlighted option.
+/// Any other key cancels with `None`.
+pub fn menu<T: AsRef<str>>(header: &str, options: &[T], width: i32, root: &mut Root) -> Option<usize> {
+    use tcod::input::KeyCode::*;
 
     let header_height = root.get_height_rect(0, 0, width, SCREEN_HEIGHT, header);
-    let height = options.len() as i32 + header_height;
-
-    let mut window = Offscreen::new(width, height);
-    window.set_default_foreground(WHITE);
-    window.print_rect_ex(
-        0,
-        0,
-        width,
-        height,
-        BackgroundFlag::None,
-        TextAlignment::Left,
-        header,
+    let page_size = menu_page_size(header_height);
+    let scrolls = options.len() > page_size;
+    let rows = cmp::min(options.len(), page_size) as i32;
+    let height = header_height + rows + if scrolls { 1 } else { 0 };
+
+    // Redraw over a copy of the screen so the translucent window does not
+    // darken what is behind it a little more on every key press.
+    let mut background = Offscreen::new(SCREEN_WIDTH, SCREEN_HEIGHT);
+    blit(
+        root,
+        (0, 0),
+        (SCREEN_WIDTH, SCREEN_HEIGHT),
+        &mut background,
+        (0, 0),
+        1.0,
+        1.0,
     );
 
-    for (index, option_text) in options.iter().enumerate() {
-        let menu_letter = (b'a' + index as u8) as char;
-        let text = format!("({}) {}", menu_letter, option_text.as_ref());
+    let mut selected = 0;
+    let mut top = 0;
 
-        window.print_ex(
+    loop {
+        let mut window = Offscreen::new(width, height);
+        window.set_default_foreground(WHITE);
+        window.print_rect_ex(
             0,
-            header_height + index as i32,
+            0,
+            width,
+            height,
             BackgroundFlag::None,
             TextAlignment::Left,
-            text,
+            header,
+        );
+
+        for (row, option_text) in options.iter().skip(top).take(page_size).enumerate() {
+            let menu_letter = (b'a' + row as u8) as char;
+            let text = format!("({}) {}", menu_letter, option_text.as_ref());
+            let y = header_height + row as i32;
+
+            if top + row == selected {
+                window.set_default_background(DARKER_SKY);
+                window.rect(0, y, width, 1, false, BackgroundFlag::Set);
+            }
+            window.print_ex(0, y, BackgroundFlag::None, TextAlignment::Left, text);
+        }
+
+        if scrolls {
+            window.set_default_foreground(LIGHT_GREY);
+            window.print_ex(
+                0,
+                height - 1,
+                BackgroundFlag::None,
+                TextAlignment::Left,
+                format!(
+                    "{}-{} of {}, arrows and PgUp/PgDn to scroll",
+                    top + 1,
+                    cmp::min(top + page_size, options.len()),
+                    options.len()
+                ),
+            );
+        }
+
+        let x = SCREEN_WIDTH / 2 - width / 2;
+        let y = SCREEN_HEIGHT / 2 - height / 2;
+        blit(
+            &background,
+            (0, 0),
+            (SCREEN_WIDTH, SCREEN_HEIGHT),
+            root,
+            (0, 0),
+            1.0,
+            1.0,
         );
+        blit(&window, (0, 0), (width, height), root, (x, y), 1.0, 0.7);
+
+        root.flush();
+        let key = root.wait_for_keypress(true);
+
+        if options.is_empty() {
+            return None;
+        }
+
+        let last = options.len() - 1;
+        match key.code {
+            Up | NumPad8 => selected = selected.saturating_sub(1),
+            Down | NumPad2 => selected = cmp::min(selected + 1, last),
+            PageUp | NumPad9 => selected = selected.saturating_sub(page_size),
+            PageDown | NumPad3 => selected = cmp::min(selected + page_size, last),
+            Home | NumPad7 => selected = 0,
+            End | NumPad1 => selected = last,
+            Enter | NumPadEnter => return Some(selected),
+            _ if key.printable.is_ascii_alphabetic() => {
+                let row = key.printable.to_ascii_lowercase() as usize - 'a' as usize;
+                let index = top + row;
+                return if row < page_size && index < options.len() {
+                    Some(index)
+                } else {
+                    None
+                };
+            }
+            _ => return None,
+        }
+
+        if selected < top {
+            top = selected;
+        } else if selected >= top + page_size {
+            top = selected + 1 - page_size;
+        }
     }
+}
 
-    let x = SCREEN_WIDTH / 2 - width / 2;
-    let y = SCREEN_HEIGHT / 2 - height / 2;
-    blit(&window, (0, 0), (width, height), root, (x, y), 1.0, 0.7);
+pub fn msgbox(text: &str, width: i32, root: &mut Root) {
+    let options: &[&str] = &[];
+    menu(text, options, width, root);
+}
 
-    root.flush();
-    let key = root.wait_for_keypress(true);
+pub fn bindings_screen(bindings: &KeyBindings, root: &mut Root) {
+    let mut text = "Key bindings\n\n".to_owned();
+    for line in bindings.describe() {
+        text.push_str(&line);
+        text.push('\n');
+    }
+    text.push_str("\nPress any key to continue.");
+    msgbox(&text, BINDINGS_SCREEN_WIDTH, root);
+}
+
+/// Breaks a message into lines of at most `width` characters at spaces.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = vec![];
+    let mut line = String::new();
+
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > width {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// Reads a line of text typed into the bottom row of the screen. Enter
+/// accepts it and Escape gives up with `None`.
+fn read_line(prompt: &str, root: &mut Root) -> Option<String> {
+    use tcod::input::KeyCode::*;
 
-    if key.printable.is_alphabetic() {
-        let index = key.printable.to_ascii_lowercase() as usize - 'a' as usize;
-        if index < options.len() {
-            Some(index)
+    let mut text = String::new();
+    loop {
+        root.set_default_background(BLACK);
+        root.rect(0, SCREEN_HEIGHT - 1, SCREEN_WIDTH, 1, true, BackgroundFlag::Set);
+        root.set_default_foreground(WHITE);
+        root.print_ex(
+            1,
+            SCREEN_HEIGHT - 1,
+            BackgroundFlag::None,
+            TextAlignment::Left,
+            format!("{}{}_", prompt, text),
+        );
+        root.flush();
+
+        let key = root.wait_for_keypress(true);
+        match key.code {
+            Enter | NumPadEnter => return Some(text),
+            Escape => return None,
+            Backspace => {
+                text.pop();
+            }
+            _ if key.printable != '\0' && !key.printable.is_control() => {
+                text.push(key.printable)
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Full-screen history of the kept messages, newest at the bottom. The
+/// arrows and PageUp/PageDown scroll, `/` searches and Escape clears the
+/// search or closes the log.
+pub fn message_log(messages: &Messages, root: &mut Root) {
+    use tcod::input::KeyCode::*;
+
+    let rows = (SCREEN_HEIGHT - 2) as usize;
+    let mut query = String::new();
+    // How many lines the view is scrolled up from the newest message.
+    let mut offset = 0;
+
+    loop {
+        let needle = query.to_lowercase();
+        let lines: Vec<(String, Color)> = messages
+            .iter()
+            .filter(|(msg, _)| msg.to_lowercase().contains(&needle))
+            .flat_map(|(msg, color)| {
+                let color = tcod_color(*color);
+                wrap(msg, LOG_WIDTH as usize)
+                    .into_iter()
+                    .map(move |line| (line, color))
+            })
+            .collect();
+        let max_offset = lines.len().saturating_sub(rows);
+        offset = cmp::min(offset, max_offset);
+        let first = max_offset - offset;
+
+        root.set_default_background(BLACK);
+        root.clear();
+        root.set_default_foreground(WHITE);
+        let title = if query.is_empty() {
+            format!("Message log, {} lines", lines.len())
         } else {
-            None
+            format!("Message log, {} lines matching \"{}\"", lines.len(), query)
+        };
+        root.print_ex(1, 0, BackgroundFlag::None, TextAlignment::Left, title);
+
+        for (row, (line, color)) in lines.iter().skip(first).take(rows).enumerate() {
+            root.set_default_foreground(*color);
+            root.print_ex(1, 1 + row as i32, BackgroundFlag::None, TextAlignment::Left, line);
+        }
+
+        root.set_default_foreground(LIGHT_GREY);
+        root.print_ex(
+            1,
+            SCREEN_HEIGHT - 1,
+            BackgroundFlag::None,
+            TextAlignment::Left,
+            "Arrows and PgUp/PgDn to scroll, / to search, Escape to close.",
+        );
+        root.flush();
+
+        let key = root.wait_for_keypress(true);
+        match key.code {
+            Up | NumPad8 => offset = cmp::min(offset + 1, max_offset),
+            Down | NumPad2 => offset = offset.saturating_sub(1),
+            PageUp | NumPad9 => offset = cmp::min(offset + rows, max_offset),
+            PageDown | NumPad3 => offset = offset.saturating_sub(rows),
+            Home | NumPad7 => offset = max_offset,
+            End | NumPad1 => offset = 0,
+            Escape if !query.is_empty() => {
+                query.clear();
+                offset = 0;
+            }
+            Escape => return,
+            _ if key.printable == '/' => {
+                if let Some(text) = read_line("Search: ", root) {
+                    query = text;
+                    offset = 0;
+                }
+            }
+            _ => {}
         }
-    } else {
-        None
     }
 }
 
 pub fn inventory_menu(inventory: &[Object], header: &str, root: &mut Root) -> Option<usize> {
-    let options = if inventory.len() == 0 {
+    let options = if inventory.is_empty() {
         vec!["Inventory is empty.".into()]
     } else {
-        inventory.iter().map(|item| item.name.clone()).collect()
+        inventory
+            .iter()
+            .map(|item| match item.equipment {
+                Some(equipment) if equipment.equipped => {
+                    format!("{} (on {})", item.name, equipment.slot)
+                }
+                _ => item.stack_name(),
+            })
+            .collect()
     };
 
     let inventory_index = menu(header, &options, INVENTORY_WIDTH, root);
 
-    if inventory.len() > 0 {
+    if !inventory.is_empty() {
         inventory_index
     } else {
         None
@@ -1,38 +1,53 @@
-use super::gamemap::{is_blocked, Map, Rect};
-use super::{Game};
+use super::colors::*;
+use super::game::{Event, Game};
+use super::gamemap::{is_blocked, Map};
+use super::mapgen::Region;
+use super::pathfinding::find_path;
+use super::templates::Templates;
+use rand::seq::SliceRandom;
 use rand::Rng;
-use tcod::colors::*;
-use tcod::console::*;
-
-const MAX_ROOM_MONSTERS: i32 = 3;
-const MAX_ROOM_ITEMS: i32 = 2;
+use serde::{Deserialize, Serialize};
+use std::fmt;
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Object {
     pub x: i32,
     pub y: i32,
-    char: char,
-    color: Color,
+    pub char: char,
+    pub color: Color,
     pub name: String,
     pub blocks: bool,
     pub alive: bool,
+    pub always_visible: bool,
+    pub level: i32,
     pub fighter: Option<Fighter>,
     pub ai: Option<Ai>,
     pub item: Option<Item>,
+    pub equipment: Option<Equipment>,
+    /// How many identical items this object stands for, see `stacks_with`.
+    pub count: u32,
+    /// Saved up towards the next action, see `game::TURN_ENERGY`.
+    pub energy: i32,
 }
 
 impl Object {
     pub fn new(x: i32, y: i32, char: char, name: &str, color: Color, blocks: bool) -> Self {
         Object {
-            x: x,
-            y: y,
-            char: char,
-            color: color,
-            blocks: blocks,
+            x,
+            y,
+            char,
+            color,
+            blocks,
             name: name.to_owned(),
             alive: false,
+            always_visible: false,
+            level: 1,
             fighter: None,
             ai: None,
             item: None,
+            equipment: None,
+            count: 1,
+            energy: 0,
         }
     }
 
@@ -43,16 +58,15 @@ impl Object {
         ((dx.pow(2) + dy.pow(2)) as f32).sqrt()
     }
 
+    pub fn distance(&self, x: i32, y: i32) -> f32 {
+        (((x - self.x).pow(2) + (y - self.y).pow(2)) as f32).sqrt()
+    }
+
     pub fn move_by(&mut self, dx: i32, dy: i32) {
         self.x += dx;
         self.y += dy;
     }
 
-    pub fn draw(&self, con: &mut dyn Console) {
-        con.set_default_foreground(self.color);
-        con.put_char(self.x, self.y, self.char, BackgroundFlag::None);
-    }
-
     pub fn pos(&self) -> (i32, i32) {
         (self.x, self.y)
     }
@@ -63,10 +77,22 @@ impl Object {
     }
 
     pub fn attack(&mut self, target: &mut Object, game: &mut Game) {
-        let damage = self.fighter.map_or(0, |f| f.power) - target.fighter.map_or(0, | f| f.defense);
+        let damage = self.power(game) - target.defense(game);
+        game.events.push(Event::Attacked {
+            attacker: self.name.clone(),
+            target: target.name.clone(),
+            damage: damage.max(0),
+        });
         if damage > 0 {
             game.messages.add(format!("{} attacks {} for {} hit points", self.name, target.name, damage), RED);
-            target.take_damage(damage, game);
+            let victim = target.fighter.map(|f| f.on_death);
+            if let Some(xp) = target.take_damage(damage, game) {
+                if victim == Some(DeathCallback::Player) {
+                    game.stats.cause_of_death =
+                        Some(format!("killed by {}", with_article(&self.name)));
+                }
+                self.gain_xp(xp);
+            }
         } else {
             game.messages.add(
             format!(
@@ -76,7 +102,17 @@ impl Object {
         }
     }
 
-    pub fn take_damage(&mut self, damage: i32, game: &mut Game) {
+    /// Returns the experience the victim was worth if this killed it.
+    pub fn take_damage(&mut self, damage: i32, game: &mut Game) -> Option<i32> {
+        self.hurt(damage, true, game)
+    }
+
+    /// `take_damage` for callers that put the death in their own message.
+    pub fn take_damage_quietly(&mut self, damage: i32, game: &mut Game) -> Option<i32> {
+        self.hurt(damage, false, game)
+    }
+
+    fn hurt(&mut self, damage: i32, announce: bool, game: &mut Game) -> Option<i32> {
         if let Some(fighter) = self.fighter.as_mut() {
             if damage > 0 {
                 fighter.hp -= damage;
@@ -86,145 +122,245 @@ impl Object {
         if let Some(fighter) = self.fighter {
             if fighter.hp <= 0 {
                 self.alive = false;
-                fighter.on_death.callback(self, game);
+                fighter.on_death.callback(self, announce, game);
+                return Some(fighter.xp);
             }
         }
+
+        None
     }
-    pub fn heal(&mut self, amount: i32) {
+
+    pub fn gain_xp(&mut self, xp: i32) {
+        if let Some(ref mut fighter) = self.fighter {
+            fighter.xp += xp;
+        }
+    }
+
+    pub fn heal(&mut self, amount: i32, game: &Game) {
+        let max_hp = self.max_hp(game);
         if let Some(ref mut fighter) = self.fighter {
             fighter.hp += amount;
-            if fighter.hp > fighter.max_hp {
-                fighter.hp = fighter.max_hp;
+            if fighter.hp > max_hp {
+                fighter.hp = max_hp;
             }
         }
     }
+
+    pub fn power(&self, game: &Game) -> i32 {
+        let base_power = self.fighter.map_or(0, |f| f.power);
+        let bonus: i32 = self
+            .get_all_equipped(game)
+            .iter()
+            .map(|e| e.power_bonus)
+            .sum();
+        base_power + bonus
+    }
+
+    pub fn defense(&self, game: &Game) -> i32 {
+        let base_defense = self.fighter.map_or(0, |f| f.defense);
+        let bonus: i32 = self
+            .get_all_equipped(game)
+            .iter()
+            .map(|e| e.defense_bonus)
+            .sum();
+        base_defense + bonus
+    }
+
+    pub fn max_hp(&self, game: &Game) -> i32 {
+        let base_max_hp = self.fighter.map_or(0, |f| f.max_hp);
+        let bonus: i32 = self
+            .get_all_equipped(game)
+            .iter()
+            .map(|e| e.max_hp_bonus)
+            .sum();
+        base_max_hp + bonus
+    }
+
+    /// Identical usable items share one inventory entry. Equipment never
+    /// stacks because each piece is worn on its own.
+    pub fn stacks_with(&self, other: &Object) -> bool {
+        self.item.is_some()
+            && self.equipment.is_none()
+            && other.equipment.is_none()
+            && self.item == other.item
+            && self.name == other.name
+    }
+
+    /// The name with the stack size in front, like "3 healing potions".
+    pub fn stack_name(&self) -> String {
+        if self.count == 1 {
+            self.name.clone()
+        } else {
+            format!("{} {}", self.count, plural(&self.name))
+        }
+    }
+
+    /// Only the player carries an inventory, so monsters never have
+    /// anything equipped.
+    pub fn get_all_equipped(&self, game: &Game) -> Vec<Equipment> {
+        let is_player = self
+            .fighter
+            .is_some_and(|f| f.on_death == DeathCallback::Player);
+        if is_player {
+            game.inventory
+                .iter()
+                .filter_map(|item| item.equipment)
+                .filter(|equipment| equipment.equipped)
+                .collect()
+        } else {
+            vec![]
+        }
+    }
+}
+
+/// "orc" becomes "an orc", "troll" becomes "a troll".
+pub fn with_article(name: &str) -> String {
+    let article = match name.chars().next() {
+        Some('a') | Some('e') | Some('i') | Some('o') | Some('u') => "an",
+        _ => "a",
+    };
+    format!("{} {}", article, name)
+}
+
+/// "scroll of fireball" becomes "scrolls of fireball", anything else just
+/// gets an "s".
+pub fn plural(name: &str) -> String {
+    match name.find(" of ") {
+        Some(index) => format!("{}s{}", &name[..index], &name[index..]),
+        None => format!("{}s", name),
+    }
 }
 
-pub fn place_objects(room: &Rect, map: &Map, objects: &mut Vec<Object>) {
-    let num_mosters = rand::thread_rng().gen_range(0, MAX_ROOM_MONSTERS + 1);
+/// Fills a room or other region with monsters and items drawn from the
+/// level's spawn tables, up to the room limits for that level.
+pub fn place_objects(
+    region: &Region,
+    map: &Map,
+    objects: &mut Vec<Object>,
+    level: u32,
+    templates: &Templates,
+    rng: &mut impl Rng,
+) {
+    let monsters = templates.monster_table(level);
+    let num_mosters = rng.gen_range(0, templates.room_limits.max_monsters.at(level) + 1);
 
     for _ in 0..num_mosters {
-        let x = rand::thread_rng().gen_range(room.x1 + 1, room.x2);
-        let y = rand::thread_rng().gen_range(room.y1 + 1, room.y2);
+        let (x, y) = match region.tiles.choose(rng) {
+            Some(&tile) => tile,
+            None => continue,
+        };
 
         if !is_blocked(x, y, map, objects) {
-            let mut monster = if rand::random::<f32>() < 0.8 {
-                let mut orc = Object::new(x, y, 'o', "orc", DESATURATED_GREEN, true);
-                orc.fighter = Some(Fighter {
-                    max_hp: 10,
-                    hp: 10,
-                    defense: 0,
-                    power: 3,
-                    on_death: DeathCallback::Monster,
-                });
-                orc.ai = Some(Ai::Basic);
-
-                orc
-            } else {
-                let mut troll = Object::new(x, y, 'T', "troll", DARKER_GREEN, true);
-                troll.fighter = Some(Fighter {
-                    max_hp: 16,
-                    hp: 16,
-                    defense: 1,
-                    power: 4,
-                    on_death: DeathCallback::Monster,
-                });
-                troll.ai = Some(Ai::Basic);
-
-                troll
-            };
-
-            monster.alive = true;
-            objects.push(monster);
+            if let Some(monster) = monsters.choose(rng) {
+                objects.push(monster.spawn(x, y));
+            }
         }
     }
 
-    let num_items = rand::thread_rng().gen_range(0, MAX_ROOM_ITEMS + 1);
+    let items = templates.item_table(level);
+    let num_items = rng.gen_range(0, templates.room_limits.max_items.at(level) + 1);
 
     for _ in 0..num_items {
-        let x = rand::thread_rng().gen_range(room.x1 + 1, room.x2);
-        let y = rand::thread_rng().gen_range(room.y1 + 1, room.y2);
+        let (x, y) = match region.tiles.choose(rng) {
+            Some(&tile) => tile,
+            None => continue,
+        };
 
         if !is_blocked(x, y, map, objects) {
-            let dice = rand::random::<f32>();
-            let item = if dice < 0.7 {
-                let mut object = Object::new(x, y, '!', "healing potion", VIOLET, false);
-                object.item = Some(Item::Heal);
-                object
-            } else if dice < 0.7 + 0.1 {
-                let mut object = Object::new(x, y, '#', "scroll of lightning bolt", LIGHT_YELLOW, false);
-                object.item = Some(Item::Lightning);
-                object
-            } else {
-                let mut object = Object::new(x, y, '#', "scroll of confusion", LIGHT_YELLOW, false);
-                object.item = Some(Item::Confuse);
-                object
-            };
-
-            objects.push(item);
+            if let Some(item) = items.choose(rng) {
+                objects.push(item.spawn(x, y));
+            }
         }
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Fighter {
     pub max_hp: i32,
     pub hp: i32,
     pub defense: i32,
     pub power: i32,
+    /// Experience a monster is worth when killed, or the player's total.
+    pub xp: i32,
+    /// Energy gained per tick of game time, see `game::TURN_ENERGY`.
+    pub speed: i32,
     pub on_death: DeathCallback,
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Ai {
+    /// Chases the player while it can see them, but waits at closed doors.
     Basic,
+    /// Chases the player like `Basic` and opens doors on the way. Once the
+    /// player is out of sight it heads for the spot it last saw them at.
+    Hunter { last_seen: Option<(i32, i32)> },
     Confused {
         previous_ai: Box<Ai>,
         num_turns: i32,
-    }
+    },
 }
 
-pub fn move_towards(id: usize, target_x: i32, target_y: i32, map: &Map, objects: &mut Vec<Object>) {
-    let dx = target_x - objects[id].x;
-    let dy = target_y - objects[id].y;
-    let distance = ((dx.pow(2) +dy.pow(2)) as f32).sqrt();
-
-    let dx = (dx as f32 / distance).round() as i32;
-    let dy = (dy as f32 / distance).round() as i32;
-
-    let x = objects[id].x + dx;
-    let y = objects[id].y + dy;
-
-    if !is_blocked(x, y, map, objects) {
-        objects[id].set_pos(x, y);
+/// Takes one step along the cheapest path to the target. If the next tile
+/// is taken by another blocking object the monster waits for it to clear.
+/// A closed door in the way is returned instead, for the monster to open.
+pub fn move_towards(
+    id: usize,
+    target_x: i32,
+    target_y: i32,
+    map: &Map,
+    objects: &mut [Object],
+) -> Option<(i32, i32)> {
+    let path = find_path(objects[id].pos(), (target_x, target_y), map, objects);
+
+    if let Some(&(x, y)) = path.as_ref().and_then(|path| path.first()) {
+        if map[x as usize][y as usize].is_closed_door() {
+            return Some((x, y));
+        }
+        if !is_blocked(x, y, map, objects) {
+            objects[id].set_pos(x, y);
+        }
     }
+    None
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub enum DeathCallback {
     Player,
     Monster,
 }
 
 impl DeathCallback {
-    fn callback(self, object: &mut Object, game: &mut Game) {
+    /// Runs the death handler, which says who died unless `announce` is
+    /// false.
+    fn callback(self, object: &mut Object, announce: bool, game: &mut Game) {
         use DeathCallback::*;
-        let callback: fn(&mut Object, game: &mut Game) = match self {
+        let callback: fn(&mut Object, bool, game: &mut Game) = match self {
             Player => player_death,
             Monster => monster_death,
         };
-        callback(object, game);
+        callback(object, announce, game);
     }
 }
 
-fn player_death(player: &mut Object, game: &mut Game) {
-    game.messages.add("You died", RED);
+fn player_death(player: &mut Object, announce: bool, game: &mut Game) {
+    if announce {
+        game.messages.add("You died", RED);
+    }
+    game.events.push(Event::Died {
+        name: player.name.clone(),
+    });
     player.char = '%';
     player.color = DARK_RED;
 }
 
-fn monster_death(monster: &mut Object, game: &mut Game) {
-    game.messages.add(format!("{} died!", monster.name), ORANGE);
+fn monster_death(monster: &mut Object, announce: bool, game: &mut Game) {
+    if announce {
+        game.messages.add(format!("{} died!", monster.name), ORANGE);
+    }
+    *game.stats.kills.entry(monster.name.clone()).or_insert(0) += 1;
+    game.events.push(Event::Died {
+        name: monster.name.clone(),
+    });
     monster.char = '%';
     monster.color = DARK_RED;
     monster.blocks = false;
@@ -233,9 +369,39 @@ fn monster_death(monster: &mut Object, game: &mut Game) {
     monster.name = format!("remains of {}", monster.name);
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Item {
     Heal,
     Lightning,
     Confuse,
+    Fireball,
+    Equipment,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Equipment {
+    pub slot: Slot,
+    pub equipped: bool,
+    pub power_bonus: i32,
+    pub defense_bonus: i32,
+    pub max_hp_bonus: i32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Slot {
+    MainHand,
+    OffHand,
+    Body,
+    Head,
+}
+
+impl fmt::Display for Slot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Slot::MainHand => write!(f, "main hand"),
+            Slot::OffHand => write!(f, "off hand"),
+            Slot::Body => write!(f, "body"),
+            Slot::Head => write!(f, "head"),
+        }
+    }
 }
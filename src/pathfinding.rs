@@ -0,0 +1,141 @@
+use super::gamemap::Map;
+use super::object::Object;
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+const STRAIGHT_COST: i32 = 10;
+const DIAGONAL_COST: i32 = 14;
+
+/// Extra cost of walking through a tile another blocking object stands on.
+/// High enough that a short detour wins, low enough that a monster still
+/// queues up behind its friends in a corridor instead of giving up.
+const OCCUPIED_COST: i32 = 100;
+
+/// Extra cost of a closed door, for the turn spent opening it.
+const CLOSED_DOOR_COST: i32 = STRAIGHT_COST;
+
+const NEIGHBOURS: [(i32, i32); 8] = [
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-1, 0),
+    (1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Node {
+    estimate: i32,
+    pos: (i32, i32),
+}
+
+impl Ord for Node {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.estimate
+            .cmp(&other.estimate)
+            .then_with(|| self.pos.cmp(&other.pos))
+    }
+}
+
+impl PartialOrd for Node {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+fn heuristic(from: (i32, i32), to: (i32, i32)) -> i32 {
+    let dx = (from.0 - to.0).abs();
+    let dy = (from.1 - to.1).abs();
+    STRAIGHT_COST * (dx + dy) + (DIAGONAL_COST - 2 * STRAIGHT_COST) * dx.min(dy)
+}
+
+/// A* search over the map's walkable tiles, moving in eight directions.
+/// Walls are impassable, while closed doors and tiles holding another
+/// blocking object only cost more, so the path may lead through them.
+/// Returns the steps after `from` up to and including `to`, or `None` if
+/// `to` cannot be reached.
+pub fn find_path(
+    from: (i32, i32),
+    to: (i32, i32),
+    map: &Map,
+    objects: &[Object],
+) -> Option<Vec<(i32, i32)>> {
+    if from == to {
+        return Some(vec![]);
+    }
+
+    let width = map.len() as i32;
+    let height = map.first().map_or(0, |column| column.len() as i32);
+    let index = |(x, y): (i32, i32)| (x * height + y) as usize;
+
+    let mut occupied = vec![false; (width * height) as usize];
+    for object in objects {
+        let (x, y) = object.pos();
+        if object.blocks && x >= 0 && y >= 0 && x < width && y < height {
+            occupied[index((x, y))] = true;
+        }
+    }
+
+    let mut cost = vec![i32::MAX; (width * height) as usize];
+    let mut came_from = vec![None; (width * height) as usize];
+    let mut open = BinaryHeap::new();
+
+    cost[index(from)] = 0;
+    open.push(Reverse(Node {
+        estimate: heuristic(from, to),
+        pos: from,
+    }));
+
+    while let Some(Reverse(Node { pos, .. })) = open.pop() {
+        if pos == to {
+            let mut path = vec![to];
+            let mut current = to;
+            while let Some(previous) = came_from[index(current)] {
+                if previous == from {
+                    break;
+                }
+                path.push(previous);
+                current = previous;
+            }
+            path.reverse();
+            return Some(path);
+        }
+
+        for &(dx, dy) in NEIGHBOURS.iter() {
+            let next = (pos.0 + dx, pos.1 + dy);
+            if next.0 < 0 || next.1 < 0 || next.0 >= width || next.1 >= height {
+                continue;
+            }
+            let tile = &map[next.0 as usize][next.1 as usize];
+            if !tile.is_walkable() {
+                continue;
+            }
+
+            let mut step = if dx != 0 && dy != 0 {
+                DIAGONAL_COST
+            } else {
+                STRAIGHT_COST
+            };
+            if next != to && occupied[index(next)] {
+                step += OCCUPIED_COST;
+            }
+            if tile.is_closed_door() {
+                step += CLOSED_DOOR_COST;
+            }
+
+            let next_cost = cost[index(pos)] + step;
+            if next_cost < cost[index(next)] {
+                cost[index(next)] = next_cost;
+                came_from[index(next)] = Some(pos);
+                open.push(Reverse(Node {
+                    estimate: next_cost + heuristic(next, to),
+                    pos: next,
+                }));
+            }
+        }
+    }
+
+    None
+}
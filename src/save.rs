@@ -0,0 +1,105 @@
+use super::game::Game;
+use super::object::Object;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+
+const SAVE_FILE: &str = "savegame";
+
+/// Bump whenever the layout of anything stored in the save file changes.
+const SAVE_VERSION: u32 = 10;
+
+#[derive(Serialize)]
+struct SaveFile<'a> {
+    version: u32,
+    game: &'a Game,
+    objects: &'a [Object],
+}
+
+#[derive(Deserialize)]
+struct SaveHeader {
+    #[serde(default)]
+    version: u32,
+}
+
+#[derive(Deserialize)]
+struct LoadedSave {
+    game: Game,
+    objects: Vec<Object>,
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(io::Error),
+    Format(serde_json::Error),
+    Version { found: u32, expected: u32 },
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveError::Io(e) => write!(f, "cannot access save file: {}", e),
+            SaveError::Format(e) => write!(f, "save file is corrupted: {}", e),
+            SaveError::Version { found, expected } => write!(
+                f,
+                "save file has version {} but this game reads version {}",
+                found, expected
+            ),
+        }
+    }
+}
+
+impl Error for SaveError {}
+
+impl From<io::Error> for SaveError {
+    fn from(e: io::Error) -> Self {
+        SaveError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for SaveError {
+    fn from(e: serde_json::Error) -> Self {
+        SaveError::Format(e)
+    }
+}
+
+pub fn save_game(game: &Game, objects: &[Object]) -> Result<(), SaveError> {
+    let save = SaveFile {
+        version: SAVE_VERSION,
+        game,
+        objects,
+    };
+    let data = serde_json::to_string(&save)?;
+    fs::write(SAVE_FILE, data)?;
+    Ok(())
+}
+
+/// Returns `Ok(None)` when there is no saved game to continue.
+pub fn load_game() -> Result<Option<(Game, Vec<Object>)>, SaveError> {
+    let data = match fs::read_to_string(SAVE_FILE) {
+        Ok(data) => data,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    let header: SaveHeader = serde_json::from_str(&data)?;
+    if header.version != SAVE_VERSION {
+        return Err(SaveError::Version {
+            found: header.version,
+            expected: SAVE_VERSION,
+        });
+    }
+
+    let mut save: LoadedSave = serde_json::from_str(&data)?;
+    save.game.compute_fov(&save.objects);
+    Ok(Some((save.game, save.objects)))
+}
+
+pub fn delete_save() -> Result<(), SaveError> {
+    match fs::remove_file(SAVE_FILE) {
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => Ok(result?),
+    }
+}
@@ -0,0 +1,108 @@
+use super::templates::Templates;
+use rand::Rng;
+
+/// A value that changes with dungeon level, a number unless given another
+/// type. Each `(level, value)` step holds from its level until the next
+/// one; before the first step the value is the default, zero for numbers.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Steps<T = u32>(Vec<(u32, T)>);
+
+impl<T: Copy + Default> Steps<T> {
+    /// Steps must be sorted by level.
+    pub fn new(steps: Vec<(u32, T)>) -> Self {
+        debug_assert!(steps.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        Steps(steps)
+    }
+
+    /// The same value on every level.
+    pub fn constant(value: T) -> Self {
+        Steps(vec![(1, value)])
+    }
+
+    pub fn at(&self, level: u32) -> T {
+        self.0
+            .iter()
+            .rev()
+            .find(|&&(from, _)| from <= level)
+            .map_or_else(T::default, |&(_, value)| value)
+    }
+}
+
+/// How many monsters and items a single room may get, by dungeon level.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RoomLimits {
+    pub max_monsters: Steps,
+    pub max_items: Steps,
+}
+
+impl Default for RoomLimits {
+    fn default() -> Self {
+        RoomLimits {
+            max_monsters: Steps::constant(3),
+            max_items: Steps::constant(2),
+        }
+    }
+}
+
+/// Entries with their weight on one dungeon level. Entries that cannot
+/// appear there are left out.
+pub struct SpawnTable<'a, T> {
+    entries: Vec<(&'a T, u32)>,
+}
+
+impl<'a, T> SpawnTable<'a, T> {
+    pub fn new(entries: impl Iterator<Item = (&'a T, u32)>) -> Self {
+        SpawnTable {
+            entries: entries.filter(|&(_, weight)| weight > 0).collect(),
+        }
+    }
+
+    /// Picks an entry with a chance proportional to its weight, or `None`
+    /// if the table is empty.
+    pub fn choose(&self, rng: &mut impl Rng) -> Option<&'a T> {
+        let total: u32 = self.entries.iter().map(|&(_, weight)| weight).sum();
+        if total == 0 {
+            return None;
+        }
+
+        let mut roll = rng.gen_range(0, total);
+        for &(entry, weight) in &self.entries {
+            if roll < weight {
+                return Some(entry);
+            }
+            roll -= weight;
+        }
+        None
+    }
+
+    /// Every entry with its chance of being picked, from 0 to 1.
+    pub fn distribution(&self) -> Vec<(&'a T, f32)> {
+        let total: u32 = self.entries.iter().map(|&(_, weight)| weight).sum();
+        self.entries
+            .iter()
+            .map(|&(entry, weight)| (entry, weight as f32 / total as f32))
+            .collect()
+    }
+}
+
+/// The spawn odds and room limits in effect on a dungeon level, one line
+/// each, for checking the balance of a templates file.
+pub fn describe_spawns(templates: &Templates, level: u32) -> Vec<String> {
+    let mut lines = vec![
+        format!("Dungeon level {}", level),
+        format!(
+            "Up to {} monsters and {} items per room",
+            templates.room_limits.max_monsters.at(level),
+            templates.room_limits.max_items.at(level)
+        ),
+        "Monsters:".to_owned(),
+    ];
+    for (monster, chance) in templates.monster_table(level).distribution() {
+        lines.push(format!("  {:>5.1}% {}", chance * 100.0, monster.name));
+    }
+    lines.push("Items:".to_owned());
+    for (item, chance) in templates.item_table(level).distribution() {
+        lines.push(format!("  {:>5.1}% {}", chance * 100.0, item.name));
+    }
+    lines
+}
@@ -0,0 +1,730 @@
+use super::colors::{self, Color};
+use super::game::NORMAL_SPEED;
+use super::mapgen::Generator;
+use super::object::*;
+use super::spawn::{RoomLimits, SpawnTable, Steps};
+use super::vault::{VaultCell, VaultTemplate};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use toml::{Spanned, Value};
+
+pub const TEMPLATES_FILE: &str = "data/templates.toml";
+
+/// The copy of `TEMPLATES_FILE` built into the game, used when there is no
+/// file to read and by headless clients.
+const BUILTIN_TEMPLATES: &str = include_str!("../data/templates.toml");
+
+const MONSTER_FIELDS: [&str; 11] = [
+    "name",
+    "glyph",
+    "color",
+    "hp",
+    "defense",
+    "power",
+    "xp",
+    "speed",
+    "ai",
+    "weight",
+    "min_depth",
+];
+
+const SPAWN_FIELDS: [&str; 2] = ["max_monsters", "max_items"];
+
+const LEVEL_FIELDS: [&str; 1] = ["generator"];
+
+const VAULT_FIELDS: [&str; 6] = ["name", "plan", "legend", "rarity", "min_depth", "max_depth"];
+
+const ITEM_FIELDS: [&str; 10] = [
+    "name",
+    "glyph",
+    "color",
+    "effect",
+    "slot",
+    "power_bonus",
+    "defense_bonus",
+    "max_hp_bonus",
+    "weight",
+    "min_depth",
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MonsterTemplate {
+    pub name: String,
+    pub glyph: char,
+    pub color: Color,
+    pub hp: i32,
+    pub defense: i32,
+    pub power: i32,
+    pub xp: i32,
+    pub speed: i32,
+    pub ai: Ai,
+    pub weight: Steps,
+    pub min_depth: u32,
+}
+
+impl MonsterTemplate {
+    pub fn spawn(&self, x: i32, y: i32) -> Object {
+        let mut monster = Object::new(x, y, self.glyph, &self.name, self.color, true);
+        monster.alive = true;
+        monster.fighter = Some(Fighter {
+            max_hp: self.hp,
+            hp: self.hp,
+            defense: self.defense,
+            power: self.power,
+            xp: self.xp,
+            speed: self.speed,
+            on_death: DeathCallback::Monster,
+        });
+        monster.ai = Some(self.ai.clone());
+        monster
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ItemTemplate {
+    pub name: String,
+    pub glyph: char,
+    pub color: Color,
+    pub item: Item,
+    pub equipment: Option<Equipment>,
+    pub weight: Steps,
+    pub min_depth: u32,
+}
+
+impl ItemTemplate {
+    pub fn spawn(&self, x: i32, y: i32) -> Object {
+        let mut object = Object::new(x, y, self.glyph, &self.name, self.color, false);
+        object.item = Some(self.item);
+        object.equipment = self.equipment;
+        object
+    }
+}
+
+/// Everything that can be spawned in the dungeon, read from a TOML file
+/// with `[[monster]]`, `[[item]]` and `[[vault]]` tables, an optional
+/// `[spawn]` table of room limits and an optional `[levels]` table choosing
+/// how each level is laid out.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Templates {
+    pub monsters: Vec<MonsterTemplate>,
+    pub items: Vec<ItemTemplate>,
+    pub vaults: Vec<VaultTemplate>,
+    pub room_limits: RoomLimits,
+    pub generator: Steps<Generator>,
+}
+
+#[derive(Debug)]
+pub enum TemplateError {
+    Io {
+        file: String,
+        error: io::Error,
+    },
+    Syntax {
+        file: String,
+        error: toml::de::Error,
+    },
+    Field {
+        file: String,
+        line: usize,
+        field: String,
+        message: String,
+    },
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TemplateError::Io { file, error } => write!(f, "cannot read {}: {}", file, error),
+            TemplateError::Syntax { file, error } => write!(f, "{}: {}", file, error),
+            TemplateError::Field {
+                file,
+                line,
+                field,
+                message,
+            } => write!(f, "{}:{}: {}: {}", file, line, field, message),
+        }
+    }
+}
+
+impl Error for TemplateError {}
+
+type Table = BTreeMap<Spanned<String>, Spanned<Value>>;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawTemplates {
+    #[serde(default)]
+    monster: Vec<Table>,
+    #[serde(default)]
+    item: Vec<Table>,
+    #[serde(default)]
+    vault: Vec<Table>,
+    spawn: Option<Table>,
+    levels: Option<Table>,
+}
+
+impl Templates {
+    /// Returns `Ok(None)` when the file does not exist.
+    pub fn load(file: &str) -> Result<Option<Templates>, TemplateError> {
+        match fs::read_to_string(file) {
+            Ok(text) => Templates::parse(file, &text).map(Some),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(TemplateError::Io {
+                file: file.to_owned(),
+                error,
+            }),
+        }
+    }
+
+    pub fn builtin() -> Templates {
+        Templates::parse(TEMPLATES_FILE, BUILTIN_TEMPLATES)
+            .unwrap_or_else(|e| panic!("built-in templates are broken: {}", e))
+    }
+
+    /// `file` is only used to point error messages at the right place.
+    pub fn parse(file: &str, text: &str) -> Result<Templates, TemplateError> {
+        let raw: RawTemplates = toml::from_str(text).map_err(|error| TemplateError::Syntax {
+            file: file.to_owned(),
+            error,
+        })?;
+
+        let monsters = raw
+            .monster
+            .iter()
+            .map(|table| {
+                let line = header_line(text, table);
+                let fields = Fields::new(file, text, "monster", table, line, &MONSTER_FIELDS)?;
+                fields.monster()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let items = raw
+            .item
+            .iter()
+            .map(|table| {
+                let line = header_line(text, table);
+                let fields = Fields::new(file, text, "item", table, line, &ITEM_FIELDS)?;
+                fields.item()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let vaults = raw
+            .vault
+            .iter()
+            .map(|table| {
+                let line = header_line(text, table);
+                let fields = Fields::new(file, text, "vault", table, line, &VAULT_FIELDS)?;
+                fields.vault(&monsters, &items)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let room_limits = match &raw.spawn {
+            Some(table) => {
+                let line = header_line(text, table);
+                Fields::new(file, text, "spawn", table, line, &SPAWN_FIELDS)?.room_limits()?
+            }
+            None => RoomLimits::default(),
+        };
+
+        let generator = match &raw.levels {
+            Some(table) => {
+                let line = header_line(text, table);
+                Fields::new(file, text, "levels", table, line, &LEVEL_FIELDS)?.generators()?
+            }
+            None => Steps::default(),
+        };
+
+        Ok(Templates {
+            monsters,
+            items,
+            vaults,
+            room_limits,
+            generator,
+        })
+    }
+
+    /// The monsters that can appear on `level`, weighted for that level.
+    pub fn monster_table(&self, level: u32) -> SpawnTable<'_, MonsterTemplate> {
+        SpawnTable::new(
+            self.monsters
+                .iter()
+                .map(|m| (m, spawn_weight(&m.weight, m.min_depth, level))),
+        )
+    }
+
+    /// The items that can appear on `level`, weighted for that level.
+    pub fn item_table(&self, level: u32) -> SpawnTable<'_, ItemTemplate> {
+        SpawnTable::new(
+            self.items
+                .iter()
+                .map(|i| (i, spawn_weight(&i.weight, i.min_depth, level))),
+        )
+    }
+
+    pub fn monster(&self, name: &str) -> Option<&MonsterTemplate> {
+        self.monsters.iter().find(|m| m.name == name)
+    }
+
+    pub fn item(&self, name: &str) -> Option<&ItemTemplate> {
+        self.items.iter().find(|i| i.name == name)
+    }
+}
+
+fn spawn_weight(weight: &Steps, min_depth: u32, level: u32) -> u32 {
+    if level < min_depth {
+        0
+    } else {
+        weight.at(level)
+    }
+}
+
+/// Line number, counted from 1, of the header of `table`: the nearest line
+/// above its first field that opens a table. Only blank lines and comments
+/// can sit in between, so headers written as `[[ monster ]]` or followed by
+/// a comment are found too.
+fn header_line(text: &str, table: &Table) -> usize {
+    let first = match table.keys().map(|key| key.start()).min() {
+        Some(offset) => offset,
+        None => return 1,
+    };
+    text[..first]
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| line.trim_start().starts_with('['))
+        .last()
+        .map_or(1, |(index, _)| index + 1)
+}
+
+/// One `[[monster]]` or `[[item]]` table, read field by field so that any
+/// error can name the field and the line it is on.
+struct Fields<'a> {
+    file: &'a str,
+    text: &'a str,
+    kind: &'a str,
+    table: &'a Table,
+    line: usize,
+}
+
+impl<'a> Fields<'a> {
+    fn new(
+        file: &'a str,
+        text: &'a str,
+        kind: &'a str,
+        table: &'a Table,
+        line: usize,
+        known: &[&str],
+    ) -> Result<Self, TemplateError> {
+        let fields = Fields {
+            file,
+            text,
+            kind,
+            table,
+            line,
+        };
+
+        for key in table.keys() {
+            if !known.contains(&key.get_ref().as_str()) {
+                return Err(fields.error(
+                    key.get_ref(),
+                    fields.line_of(key.start()),
+                    format!("unknown field, expected one of: {}", known.join(", ")),
+                ));
+            }
+        }
+
+        Ok(fields)
+    }
+
+    fn monster(&self) -> Result<MonsterTemplate, TemplateError> {
+        let ai = match self.string("ai")?.as_str() {
+            "basic" => Ai::Basic,
+            "hunter" => Ai::Hunter { last_seen: None },
+            other => {
+                return Err(self.invalid(
+                    "ai",
+                    format!("unknown ai '{}', expected basic or hunter", other),
+                ))
+            }
+        };
+
+        Ok(MonsterTemplate {
+            name: self.string("name")?,
+            glyph: self.glyph()?,
+            color: self.color()?,
+            hp: self.positive("hp")?,
+            defense: self.int("defense", None)?,
+            power: self.int("power", None)?,
+            xp: self.int("xp", None)?,
+            speed: self.positive_or("speed", NORMAL_SPEED)?,
+            ai,
+            weight: self.weight()?,
+            min_depth: self.min_depth()?,
+        })
+    }
+
+    fn item(&self) -> Result<ItemTemplate, TemplateError> {
+        let item = match self.string("effect")?.as_str() {
+            "heal" => Item::Heal,
+            "lightning" => Item::Lightning,
+            "confuse" => Item::Confuse,
+            "fireball" => Item::Fireball,
+            "equipment" => Item::Equipment,
+            other => {
+                return Err(self.invalid(
+                    "effect",
+                    format!(
+                        "unknown effect '{}', expected heal, lightning, confuse, \
+                         fireball or equipment",
+                        other
+                    ),
+                ))
+            }
+        };
+
+        let equipment = if item == Item::Equipment {
+            let slot = match self.string("slot")?.as_str() {
+                "main hand" => Slot::MainHand,
+                "off hand" => Slot::OffHand,
+                "body" => Slot::Body,
+                "head" => Slot::Head,
+                other => {
+                    return Err(self.invalid(
+                        "slot",
+                        format!(
+                            "unknown slot '{}', expected main hand, off hand, body or head",
+                            other
+                        ),
+                    ))
+                }
+            };
+            Some(Equipment {
+                slot,
+                equipped: false,
+                power_bonus: self.int("power_bonus", Some(0))?,
+                defense_bonus: self.int("defense_bonus", Some(0))?,
+                max_hp_bonus: self.int("max_hp_bonus", Some(0))?,
+            })
+        } else {
+            for field in &["slot", "power_bonus", "defense_bonus", "max_hp_bonus"] {
+                if self.table.contains_key(*field) {
+                    return Err(self.invalid(field, "only equipment takes this field".into()));
+                }
+            }
+            None
+        };
+
+        Ok(ItemTemplate {
+            name: self.string("name")?,
+            glyph: self.glyph()?,
+            color: self.color()?,
+            item,
+            equipment,
+            weight: self.weight()?,
+            min_depth: self.min_depth()?,
+        })
+    }
+
+    fn vault(
+        &self,
+        monsters: &[MonsterTemplate],
+        items: &[ItemTemplate],
+    ) -> Result<VaultTemplate, TemplateError> {
+        let mut legend = BTreeMap::new();
+        if let Some(value) = self.get("legend") {
+            let table = value
+                .get_ref()
+                .as_table()
+                .ok_or_else(|| self.invalid("legend", "expected a table of markers".into()))?;
+            for (marker, name) in table {
+                let mut chars = marker.chars();
+                let marker = match (chars.next(), chars.next()) {
+                    (Some(c), None) if c != ' ' && c != '#' && c != '.' => c,
+                    _ => {
+                        return Err(self.invalid(
+                            "legend",
+                            format!(
+                                "marker \"{}\" must be one character other than space, # or .",
+                                marker
+                            ),
+                        ))
+                    }
+                };
+                let name = name.as_str().unwrap_or_default();
+                let cell = if monsters.iter().any(|m| m.name == name) {
+                    VaultCell::Monster(name.to_owned())
+                } else if items.iter().any(|i| i.name == name) {
+                    VaultCell::Item(name.to_owned())
+                } else {
+                    return Err(self.invalid(
+                        "legend",
+                        format!("'{}' is not a monster or item in this file", name),
+                    ));
+                };
+                legend.insert(marker, cell);
+            }
+        }
+
+        let text = self.string("plan")?;
+        let lines: Vec<&str> = text.trim_end().lines().collect();
+        let width = lines
+            .iter()
+            .map(|line| line.chars().count())
+            .max()
+            .unwrap_or(0);
+        let mut plan = vec![];
+        for line in lines {
+            let mut row = vec![];
+            for c in line.chars() {
+                row.push(match c {
+                    ' ' => VaultCell::Outside,
+                    '#' => VaultCell::Wall,
+                    '.' => VaultCell::Floor,
+                    other => legend.get(&other).cloned().ok_or_else(|| {
+                        self.invalid(
+                            "plan",
+                            format!("unknown marker '{}', add it to the legend", other),
+                        )
+                    })?,
+                });
+            }
+            row.resize(width, VaultCell::Outside);
+            plan.push(row);
+        }
+
+        let max_depth = match self.get("max_depth") {
+            Some(_) => Some(self.positive("max_depth")? as u32),
+            None => None,
+        };
+        let vault = VaultTemplate {
+            name: self.string("name")?,
+            plan,
+            rarity: self.positive_or("rarity", 1)? as u32,
+            min_depth: self.min_depth()?,
+            max_depth,
+        };
+        vault
+            .check_plan()
+            .map_err(|message| self.invalid("plan", message))?;
+        Ok(vault)
+    }
+
+    fn get(&self, field: &str) -> Option<&'a Spanned<Value>> {
+        self.table.get(field)
+    }
+
+    fn required(&self, field: &str) -> Result<&'a Spanned<Value>, TemplateError> {
+        self.get(field)
+            .ok_or_else(|| self.error(field, self.line, "missing field".into()))
+    }
+
+    fn string(&self, field: &str) -> Result<String, TemplateError> {
+        match self.required(field)?.get_ref() {
+            Value::String(s) => Ok(s.clone()),
+            other => Err(self.invalid(
+                field,
+                format!("expected a string, found {}", other.type_str()),
+            )),
+        }
+    }
+
+    /// Reads an integer field, falling back to `default` when it is left
+    /// out. Without a default the field is required.
+    fn int(&self, field: &str, default: Option<i32>) -> Result<i32, TemplateError> {
+        let value = match (self.get(field), default) {
+            (Some(value), _) => value,
+            (None, Some(default)) => return Ok(default),
+            (None, None) => return Err(self.error(field, self.line, "missing field".into())),
+        };
+
+        match value.get_ref() {
+            Value::Integer(n) if *n >= i32::MIN as i64 && *n <= i32::MAX as i64 => Ok(*n as i32),
+            Value::Integer(n) => Err(self.invalid(field, format!("{} is out of range", n))),
+            other => Err(self.invalid(
+                field,
+                format!("expected an integer, found {}", other.type_str()),
+            )),
+        }
+    }
+
+    fn positive(&self, field: &str) -> Result<i32, TemplateError> {
+        self.check_positive(field, self.int(field, None)?)
+    }
+
+    fn positive_or(&self, field: &str, default: i32) -> Result<i32, TemplateError> {
+        self.check_positive(field, self.int(field, Some(default))?)
+    }
+
+    fn check_positive(&self, field: &str, value: i32) -> Result<i32, TemplateError> {
+        if value > 0 {
+            Ok(value)
+        } else {
+            Err(self.invalid(field, format!("must be above zero, found {}", value)))
+        }
+    }
+
+    fn weight(&self) -> Result<Steps, TemplateError> {
+        self.counts("weight", None)
+    }
+
+    fn room_limits(&self) -> Result<RoomLimits, TemplateError> {
+        let defaults = RoomLimits::default();
+        Ok(RoomLimits {
+            max_monsters: self.counts("max_monsters", Some(defaults.max_monsters))?,
+            max_items: self.counts("max_items", Some(defaults.max_items))?,
+        })
+    }
+
+    fn generators(&self) -> Result<Steps<Generator>, TemplateError> {
+        let expected = Generator::ALL
+            .iter()
+            .map(|g| format!("\"{}\"", g.name()))
+            .collect::<Vec<_>>()
+            .join(" or ");
+        self.steps("generator", Some(Steps::default()), &expected, |value| {
+            value.as_str().and_then(Generator::from_name)
+        })
+    }
+
+    /// Steps of numbers from zero up, see `steps`.
+    fn counts(&self, field: &str, default: Option<Steps>) -> Result<Steps, TemplateError> {
+        self.steps(field, default, "a number from 0 up", |value| {
+            value
+                .as_integer()
+                .filter(|&n| n >= 0 && n <= u32::MAX as i64)
+                .map(|n| n as u32)
+        })
+    }
+
+    /// Reads either a single value for every level or a list of
+    /// `[level, value]` steps with rising levels, such as `[[1, 80], [4, 60]]`.
+    /// `expected` describes a valid value for error messages.
+    fn steps<T: Copy + Default>(
+        &self,
+        field: &str,
+        default: Option<Steps<T>>,
+        expected: &str,
+        value_of: impl Fn(&Value) -> Option<T>,
+    ) -> Result<Steps<T>, TemplateError> {
+        let value = match (self.get(field), default) {
+            (Some(value), _) => value,
+            (None, Some(default)) => return Ok(default),
+            (None, None) => return Err(self.error(field, self.line, "missing field".into())),
+        };
+
+        let steps = match value.get_ref() {
+            Value::Array(steps) => steps,
+            other => {
+                return value_of(other).map(Steps::constant).ok_or_else(|| {
+                    self.invalid(
+                        field,
+                        format!(
+                            "expected {} or [[level, value], ...], found {}",
+                            expected, other
+                        ),
+                    )
+                })
+            }
+        };
+
+        let mut pairs = Vec::with_capacity(steps.len());
+        for step in steps {
+            let pair = step.as_array().map(Vec::as_slice).unwrap_or_default();
+            let level = pair.first().and_then(Value::as_integer).filter(|&l| l >= 1);
+            let value = pair.get(1).and_then(&value_of);
+            match (level, value) {
+                (Some(level), Some(value)) if pair.len() == 2 && level <= u32::MAX as i64 => {
+                    pairs.push((level as u32, value))
+                }
+                _ => {
+                    return Err(self.invalid(
+                        field,
+                        format!(
+                            "each step must be [level, value] with a level from 1 up and {} as \
+                             the value",
+                            expected
+                        ),
+                    ))
+                }
+            }
+        }
+
+        if pairs.is_empty() || pairs.windows(2).any(|pair| pair[0].0 >= pair[1].0) {
+            return Err(self.invalid(field, "steps must be listed by rising level".into()));
+        }
+        Ok(Steps::new(pairs))
+    }
+
+    fn min_depth(&self) -> Result<u32, TemplateError> {
+        Ok(self.positive_or("min_depth", 1)? as u32)
+    }
+
+    fn glyph(&self) -> Result<char, TemplateError> {
+        let glyph = self.string("glyph")?;
+        let mut chars = glyph.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Ok(c),
+            _ => Err(self.invalid(
+                "glyph",
+                format!("expected a single character, found \"{}\"", glyph),
+            )),
+        }
+    }
+
+    fn color(&self) -> Result<Color, TemplateError> {
+        match self.required("color")?.get_ref() {
+            Value::String(name) => colors::by_name(name)
+                .ok_or_else(|| self.invalid("color", format!("unknown color '{}'", name))),
+            Value::Array(rgb) => {
+                let channels: Vec<u8> = rgb
+                    .iter()
+                    .filter_map(|c| c.as_integer())
+                    .filter(|c| (0..=255).contains(c))
+                    .map(|c| c as u8)
+                    .collect();
+                match channels[..] {
+                    [r, g, b] if rgb.len() == 3 => Ok(Color::new(r, g, b)),
+                    _ => Err(self.invalid(
+                        "color",
+                        "expected three numbers from 0 to 255 as [r, g, b]".into(),
+                    )),
+                }
+            }
+            other => Err(self.invalid(
+                "color",
+                format!(
+                    "expected a color name or [r, g, b], found {}",
+                    other.type_str()
+                ),
+            )),
+        }
+    }
+
+    /// Error for a field that is present but has a bad value.
+    fn invalid(&self, field: &str, message: String) -> TemplateError {
+        let line = self
+            .get(field)
+            .map_or(self.line, |v| self.line_of(v.start()));
+        self.error(field, line, message)
+    }
+
+    fn error(&self, field: &str, line: usize, message: String) -> TemplateError {
+        let name = match self.get("name").map(|v| v.get_ref()) {
+            Some(Value::String(name)) => format!(" '{}'", name),
+            _ => String::new(),
+        };
+        TemplateError::Field {
+            file: self.file.to_owned(),
+            line,
+            field: format!("{}{} field `{}`", self.kind, name, field),
+            message,
+        }
+    }
+
+    fn line_of(&self, offset: usize) -> usize {
+        self.text[..offset].matches('\n').count() + 1
+    }
+}
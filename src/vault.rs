@@ -0,0 +1,304 @@
+use super::gamemap::{Map, Tile, MAP_HEIGHT, MAP_WIDTH};
+use super::mapgen::flood_fill;
+use super::object::Object;
+use super::templates::Templates;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::collections::{HashSet, VecDeque};
+
+/// Most vaults stamped into a single level.
+const MAX_VAULTS_PER_LEVEL: usize = 2;
+/// Random spots tried for each vault before giving up on it.
+const VAULT_PLACEMENT_TRIES: u32 = 40;
+
+/// One character of a vault plan.
+#[derive(Clone, Debug, PartialEq)]
+pub enum VaultCell {
+    /// A space: whatever the generator put there is left alone.
+    Outside,
+    Wall,
+    Floor,
+    /// Floor with this monster on it.
+    Monster(String),
+    /// Floor with this item on it.
+    Item(String),
+}
+
+impl VaultCell {
+    fn is_floor(&self) -> bool {
+        match self {
+            VaultCell::Outside | VaultCell::Wall => false,
+            VaultCell::Floor | VaultCell::Monster(_) | VaultCell::Item(_) => true,
+        }
+    }
+}
+
+/// A hand-made room drawn in ASCII, stamped into solid rock and tunnelled
+/// to the rest of the level through the gaps in its outer wall.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VaultTemplate {
+    pub name: String,
+    /// Rows of the plan from top to bottom, all the same length.
+    pub plan: Vec<Vec<VaultCell>>,
+    /// The vault turns up on about one level in `rarity`.
+    pub rarity: u32,
+    pub min_depth: u32,
+    pub max_depth: Option<u32>,
+}
+
+impl VaultTemplate {
+    pub fn width(&self) -> i32 {
+        self.plan.first().map_or(0, |row| row.len() as i32)
+    }
+
+    pub fn height(&self) -> i32 {
+        self.plan.len() as i32
+    }
+
+    pub fn allowed_on(&self, level: u32) -> bool {
+        level >= self.min_depth && self.max_depth.map_or(true, |max| level <= max)
+    }
+
+    fn cell(&self, x: i32, y: i32) -> &VaultCell {
+        if x < 0 || y < 0 || x >= self.width() || y >= self.height() {
+            return &VaultCell::Outside;
+        }
+        &self.plan[y as usize][x as usize]
+    }
+
+    fn cells(&self) -> impl Iterator<Item = (i32, i32, &VaultCell)> {
+        self.plan.iter().enumerate().flat_map(|(y, row)| {
+            row.iter()
+                .enumerate()
+                .map(move |(x, cell)| (x as i32, y as i32, cell))
+        })
+    }
+
+    /// Floor cells next to the outside of the vault, where tunnels join it.
+    pub fn entrances(&self) -> Vec<(i32, i32)> {
+        self.cells()
+            .filter(|(_, _, cell)| cell.is_floor())
+            .filter(|&(x, y, _)| {
+                [(-1, 0), (1, 0), (0, -1), (0, 1)]
+                    .iter()
+                    .any(|&(dx, dy)| *self.cell(x + dx, y + dy) == VaultCell::Outside)
+            })
+            .map(|(x, y, _)| (x, y))
+            .collect()
+    }
+
+    /// Checks the plan on its own: it needs floor, a way in, and no floor
+    /// cut off from the entrances.
+    pub fn check_plan(&self) -> Result<(), String> {
+        if self.width() > MAP_WIDTH - 4 || self.height() > MAP_HEIGHT - 4 {
+            return Err(format!(
+                "the plan is too big, it must fit in {} by {}",
+                MAP_WIDTH - 4,
+                MAP_HEIGHT - 4
+            ));
+        }
+
+        let floor: HashSet<_> = self
+            .cells()
+            .filter(|(_, _, cell)| cell.is_floor())
+            .map(|(x, y, _)| (x, y))
+            .collect();
+        if floor.is_empty() {
+            return Err("the plan has no floor".into());
+        }
+
+        let entrances = self.entrances();
+        if entrances.is_empty() {
+            return Err("the plan has no gap in its outer wall to enter by".into());
+        }
+
+        let mut seen = HashSet::new();
+        let mut todo = entrances;
+        while let Some((x, y)) = todo.pop() {
+            if !floor.contains(&(x, y)) || !seen.insert((x, y)) {
+                continue;
+            }
+            for dx in -1..=1 {
+                for dy in -1..=1 {
+                    todo.push((x + dx, y + dy));
+                }
+            }
+        }
+        let cut_off = floor
+            .iter()
+            .filter(|tile| !seen.contains(tile))
+            .min_by_key(|&&(x, y)| (y, x));
+        match cut_off {
+            Some((x, y)) => Err(format!(
+                "floor at column {}, row {} of the plan cannot be reached",
+                x + 1,
+                y + 1
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Stamps some of the vaults allowed on `level` into solid rock, each with
+/// the chance its rarity gives. `start` is where the player enters the
+/// level; every vault that is kept can be walked to from there.
+pub fn place_vaults(
+    map: &mut Map,
+    start: (i32, i32),
+    objects: &mut Vec<Object>,
+    level: u32,
+    templates: &Templates,
+    rng: &mut impl Rng,
+) {
+    let mut vaults: Vec<_> = templates
+        .vaults
+        .iter()
+        .filter(|vault| vault.allowed_on(level))
+        .collect();
+    vaults.shuffle(rng);
+
+    let mut placed = 0;
+    for vault in vaults {
+        if placed == MAX_VAULTS_PER_LEVEL {
+            break;
+        }
+        if !rng.gen_ratio(1, vault.rarity) {
+            continue;
+        }
+        for _ in 0..VAULT_PLACEMENT_TRIES {
+            let x = rng.gen_range(1, MAP_WIDTH - vault.width());
+            let y = rng.gen_range(1, MAP_HEIGHT - vault.height());
+            if place_vault(vault, x, y, map, start, objects, templates) {
+                placed += 1;
+                break;
+            }
+        }
+    }
+}
+
+/// Stamps `vault` with its top left corner at `(x, y)`, tunnels each
+/// entrance to the nearest floor outside and spawns its monsters and items.
+/// Leaves the map untouched and returns false if the spot is not solid
+/// rock or the vault would not be reachable from `start`.
+pub fn place_vault(
+    vault: &VaultTemplate,
+    x: i32,
+    y: i32,
+    map: &mut Map,
+    start: (i32, i32),
+    objects: &mut Vec<Object>,
+    templates: &Templates,
+) -> bool {
+    // Keep a border of rock around the vault so it does not open straight
+    // into a neighbouring room.
+    let (x1, y1) = (x - 1, y - 1);
+    let (x2, y2) = (x + vault.width(), y + vault.height());
+    if x1 < 0 || y1 < 0 || x2 >= MAP_WIDTH || y2 >= MAP_HEIGHT {
+        return false;
+    }
+    let solid =
+        (x1..=x2).all(|mx| (y1..=y2).all(|my| !map[mx as usize][my as usize].is_walkable()));
+    if !solid {
+        return false;
+    }
+
+    let mut stamped = map.clone();
+    let footprint: HashSet<_> = vault
+        .cells()
+        .filter(|(_, _, cell)| **cell != VaultCell::Outside)
+        .map(|(cx, cy, _)| (x + cx, y + cy))
+        .collect();
+    for (cx, cy, cell) in vault.cells() {
+        match cell {
+            VaultCell::Outside => {}
+            VaultCell::Wall => stamped[(x + cx) as usize][(y + cy) as usize] = Tile::wall(),
+            _ => stamped[(x + cx) as usize][(y + cy) as usize] = Tile::empty(),
+        }
+    }
+
+    for (ex, ey) in vault.entrances() {
+        match tunnel_out(&stamped, (x + ex, y + ey), &footprint) {
+            Some(path) => {
+                for &(tx, ty) in &path {
+                    stamped[tx as usize][ty as usize] = Tile::empty();
+                }
+                // Where the tunnel broke through a wall it gets a door, so
+                // it does not leave a gap next to the room's own doors.
+                if let Some(&(tx, ty)) = path.first() {
+                    if is_gap_in_wall(&stamped, tx, ty) {
+                        stamped[tx as usize][ty as usize] = Tile::door();
+                    }
+                }
+            }
+            None => return false,
+        }
+    }
+
+    let reachable: HashSet<_> = flood_fill(&stamped, start).into_iter().collect();
+    let connected = vault
+        .cells()
+        .filter(|(_, _, cell)| cell.is_floor())
+        .all(|(cx, cy, _)| reachable.contains(&(x + cx, y + cy)));
+    if !connected {
+        return false;
+    }
+
+    *map = stamped;
+    for (cx, cy, cell) in vault.cells() {
+        let spawned = match cell {
+            VaultCell::Monster(name) => templates.monster(name).map(|m| m.spawn(x + cx, y + cy)),
+            VaultCell::Item(name) => templates.item(name).map(|i| i.spawn(x + cx, y + cy)),
+            _ => None,
+        };
+        objects.extend(spawned);
+    }
+    true
+}
+
+/// True if `(x, y)` is open with walls or doors on both sides one way and
+/// open floor on both sides the other way.
+fn is_gap_in_wall(map: &Map, x: i32, y: i32) -> bool {
+    let open = |x: i32, y: i32| !map[x as usize][y as usize].blocked;
+    open(x, y)
+        && ((!open(x - 1, y) && !open(x + 1, y) && open(x, y - 1) && open(x, y + 1))
+            || (!open(x, y - 1) && !open(x, y + 1) && open(x - 1, y) && open(x + 1, y)))
+}
+
+/// The shortest tunnel from a vault entrance to floor outside the vault,
+/// not counting the entrance itself, listed from the end next to that
+/// floor. Tunnels go around the vault, never through it.
+fn tunnel_out(
+    map: &Map,
+    entrance: (i32, i32),
+    footprint: &HashSet<(i32, i32)>,
+) -> Option<Vec<(i32, i32)>> {
+    let mut came_from = vec![vec![None; MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+    let mut todo = VecDeque::new();
+    todo.push_back(entrance);
+
+    while let Some((x, y)) = todo.pop_front() {
+        for &(dx, dy) in &[(-1, 0), (1, 0), (0, -1), (0, 1)] {
+            let (nx, ny) = (x + dx, y + dy);
+            if nx < 1 || ny < 1 || nx >= MAP_WIDTH - 1 || ny >= MAP_HEIGHT - 1 {
+                continue;
+            }
+            if footprint.contains(&(nx, ny)) || came_from[nx as usize][ny as usize].is_some() {
+                continue;
+            }
+            came_from[nx as usize][ny as usize] = Some((x, y));
+
+            if map[nx as usize][ny as usize].is_walkable() {
+                let mut path = vec![];
+                let mut step = (x, y);
+                while step != entrance {
+                    path.push(step);
+                    step = came_from[step.0 as usize][step.1 as usize]?;
+                }
+                return Some(path);
+            }
+            todo.push_back((nx, ny));
+        }
+    }
+
+    None
+}
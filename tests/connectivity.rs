@@ -0,0 +1,125 @@
+use crablike::colors::WHITE;
+use crablike::gamemap::*;
+use crablike::mapgen::*;
+use crablike::object::Object;
+use crablike::templates::Templates;
+use rand::SeedableRng;
+use rand_pcg::Pcg32;
+
+fn player() -> Vec<Object> {
+    vec![Object::new(0, 0, '@', "player", WHITE, true)]
+}
+
+#[test]
+fn every_level_is_fully_reachable() {
+    let templates = Templates::builtin();
+    for seed in 0..2000 {
+        let level = 1 + (seed % 10) as u32;
+        let mut objects = player();
+        let map = make_map(
+            &mut objects,
+            level,
+            &templates,
+            &mut Pcg32::seed_from_u64(seed),
+        );
+
+        let unreachable = unreachable_spots(&map, &objects);
+        assert!(
+            unreachable.is_empty(),
+            "seed {} level {}: cannot reach {:?}",
+            seed,
+            level,
+            unreachable
+        );
+    }
+}
+
+#[test]
+fn every_generator_makes_reachable_levels() {
+    for &generator in Generator::ALL.iter() {
+        let text = format!("[levels]\ngenerator = \"{}\"\n", generator.name());
+        let templates = Templates::parse("test.toml", &text).unwrap();
+        for seed in 0..300 {
+            let mut objects = player();
+            let map = make_map(&mut objects, 1, &templates, &mut Pcg32::seed_from_u64(seed));
+            assert!(
+                unreachable_spots(&map, &objects).is_empty(),
+                "{} seed {}",
+                generator.name(),
+                seed
+            );
+        }
+    }
+}
+
+#[test]
+fn cut_off_floor_is_tunnelled_to() {
+    for seed in 0..200 {
+        let Layout { mut map, regions } = Generator::Bsp.generate(&mut Pcg32::seed_from_u64(seed));
+        let mut objects = player();
+        objects[0].set_pos(regions[0].center.0, regions[0].center.1);
+
+        // Open a pocket in the first bit of solid rock found and drop an
+        // item in it.
+        let pocket = (2..MAP_WIDTH - 2)
+            .flat_map(|x| (2..MAP_HEIGHT - 2).map(move |y| (x, y)))
+            .find(|&(x, y)| {
+                (x - 1..=x + 1)
+                    .all(|nx| (y - 1..=y + 1).all(|ny| map[nx as usize][ny as usize].blocked))
+            })
+            .expect("BSP levels leave some rock");
+        map[pocket.0 as usize][pocket.1 as usize] = Tile::empty();
+        objects.push(Object::new(pocket.0, pocket.1, '!', "potion", WHITE, false));
+        assert_eq!(unreachable_spots(&map, &objects), vec![pocket, pocket]);
+
+        connect_unreachable(&mut map, objects[0].pos());
+        assert!(
+            unreachable_spots(&map, &objects).is_empty(),
+            "seed {}",
+            seed
+        );
+    }
+}
+
+/// A one-tile room holding the player and, while `buried` is true, stairs
+/// sealed in solid rock where no tunnel is ever dug to.
+fn closet(objects: &mut Vec<Object>, buried: bool) -> Map {
+    let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+    map[10][10] = Tile::empty();
+    objects[0].set_pos(10, 10);
+    if buried {
+        objects.push(Object::new(30, 30, '>', STAIRS, WHITE, false));
+    }
+    map
+}
+
+#[test]
+fn broken_levels_are_laid_out_again() {
+    let mut attempts = 0;
+    let mut objects = player();
+    let map = make_map_with(
+        &mut objects,
+        1,
+        &mut Pcg32::seed_from_u64(1),
+        |objects, _| {
+            attempts += 1;
+            closet(objects, attempts < 3)
+        },
+    );
+
+    assert_eq!(attempts, 3);
+    assert_eq!(objects.len(), 1);
+    assert!(unreachable_spots(&map, &objects).is_empty());
+}
+
+#[test]
+#[should_panic(expected = "level 4 still has spots that cannot be reached after 10 attempts")]
+fn levels_that_stay_broken_fail_loudly() {
+    let mut objects = player();
+    make_map_with(
+        &mut objects,
+        4,
+        &mut Pcg32::seed_from_u64(1),
+        |objects, _| closet(objects, true),
+    );
+}
@@ -0,0 +1,154 @@
+use crablike::colors::WHITE;
+use crablike::game::*;
+use crablike::gamemap::{Map, Tile, MAP_HEIGHT, MAP_WIDTH};
+use crablike::mapgen::Generator;
+use crablike::object::Object;
+use rand::SeedableRng;
+use rand_pcg::Pcg32;
+
+const DOOR: (i32, i32) = (12, 10);
+
+/// Solid rock with a corridor along row 10 from column 10 to 20, shut by a
+/// door at column 12, and the player alone at its west end.
+fn corridor() -> (Game, Vec<Object>) {
+    let (mut game, mut objects) = new_game(3);
+    objects.truncate(PLAYER + 1);
+
+    game.map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+    for x in 10..=20 {
+        game.map[x][10] = Tile::empty();
+    }
+    game.map[DOOR.0 as usize][DOOR.1 as usize] = Tile::door();
+
+    objects[PLAYER].set_pos(11, 10);
+    game.compute_fov(&objects);
+    (game, objects)
+}
+
+fn door(map: &Map) -> &Tile {
+    &map[DOOR.0 as usize][DOOR.1 as usize]
+}
+
+#[test]
+fn generated_rooms_get_doors_between_walls() {
+    for &generator in &[Generator::Rooms, Generator::Bsp] {
+        let mut doors = 0;
+        for seed in 0..50 {
+            let map = generator.generate(&mut Pcg32::seed_from_u64(seed)).map;
+            let solid = |x: i32, y: i32| {
+                let tile = &map[x as usize][y as usize];
+                tile.blocked && !tile.door
+            };
+            for x in 1..MAP_WIDTH - 1 {
+                for y in 1..MAP_HEIGHT - 1 {
+                    if !map[x as usize][y as usize].door {
+                        continue;
+                    }
+                    doors += 1;
+                    assert!(map[x as usize][y as usize].is_closed_door());
+                    assert!(
+                        (solid(x - 1, y) && solid(x + 1, y))
+                            || (solid(x, y - 1) && solid(x, y + 1)),
+                        "{}, seed {}: door at ({}, {}) is not set in a wall",
+                        generator.name(),
+                        seed,
+                        x,
+                        y
+                    );
+                }
+            }
+        }
+        assert!(doors > 50, "{} made only {} doors", generator.name(), doors);
+    }
+}
+
+#[test]
+fn bumping_a_door_opens_it_and_lets_light_through() {
+    let (mut game, mut objects) = corridor();
+    assert!(!game.fov.is_in_fov(15, 10));
+
+    let events = game.apply(Command::Move { dx: 1, dy: 0 }, &mut objects);
+
+    assert!(events.contains(&Event::DoorOpened { x: 12, y: 10 }));
+    assert!(door(&game.map).is_open_door());
+    assert_eq!(objects[PLAYER].pos(), (11, 10));
+    assert!(game.fov.is_in_fov(15, 10));
+
+    game.apply(Command::Move { dx: 1, dy: 0 }, &mut objects);
+    assert_eq!(objects[PLAYER].pos(), DOOR);
+}
+
+#[test]
+fn closing_a_door_blocks_sight_again() {
+    let (mut game, mut objects) = corridor();
+    game.apply(Command::Move { dx: 1, dy: 0 }, &mut objects);
+
+    let events = game.apply(Command::CloseDoor { dx: 1, dy: 0 }, &mut objects);
+
+    assert!(events.contains(&Event::DoorClosed { x: 12, y: 10 }));
+    assert!(door(&game.map).is_closed_door());
+    assert!(!game.fov.is_in_fov(15, 10));
+
+    // Nothing to close: no turn passes.
+    let time = game.time;
+    assert!(game
+        .apply(Command::CloseDoor { dx: 1, dy: 0 }, &mut objects)
+        .is_empty());
+    assert!(game
+        .apply(Command::CloseDoor { dx: 0, dy: 1 }, &mut objects)
+        .is_empty());
+    assert_eq!(game.time, time);
+}
+
+#[test]
+fn doors_cannot_close_on_something() {
+    let (mut game, mut objects) = corridor();
+    game.apply(Command::Move { dx: 1, dy: 0 }, &mut objects);
+    objects.push(Object::new(DOOR.0, DOOR.1, '!', "potion", WHITE, false));
+
+    let events = game.apply(Command::CloseDoor { dx: 1, dy: 0 }, &mut objects);
+
+    assert!(events.is_empty());
+    assert!(door(&game.map).is_open_door());
+}
+
+/// The corridor with its door open and `monster` from the builtin
+/// templates at its far end, in sight of the player.
+fn monster_down_the_corridor(monster: &str) -> (Game, Vec<Object>) {
+    let (mut game, mut objects) = corridor();
+    game.map[DOOR.0 as usize][DOOR.1 as usize].set_door_open(true);
+    let monster = game.templates.monster(monster).unwrap().spawn(18, 10);
+    objects.push(monster);
+    game.compute_fov(&objects);
+    (game, objects)
+}
+
+#[test]
+fn hunters_open_doors_shut_in_their_face() {
+    let (mut game, mut objects) = monster_down_the_corridor("orc");
+    game.apply(Command::Wait, &mut objects);
+    game.apply(Command::CloseDoor { dx: 1, dy: 0 }, &mut objects);
+    assert!(!game.fov.is_in_fov(objects[1].x, objects[1].y));
+
+    let mut events = vec![];
+    for _ in 0..6 {
+        events.extend(game.apply(Command::Wait, &mut objects));
+    }
+
+    assert!(events.contains(&Event::DoorOpened { x: 12, y: 10 }));
+    assert!(door(&game.map).is_open_door());
+    assert!(game.fov.is_in_fov(objects[1].x, objects[1].y));
+}
+
+#[test]
+fn basic_monsters_wait_at_closed_doors() {
+    let (mut game, mut objects) = monster_down_the_corridor("troll");
+    game.apply(Command::Wait, &mut objects);
+    game.apply(Command::CloseDoor { dx: 1, dy: 0 }, &mut objects);
+
+    for _ in 0..6 {
+        game.apply(Command::Wait, &mut objects);
+    }
+
+    assert!(door(&game.map).is_closed_door());
+}
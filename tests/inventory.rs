@@ -0,0 +1,124 @@
+use crablike::colors::VIOLET;
+use crablike::game::*;
+use crablike::gamemap::{Tile, MAP_HEIGHT, MAP_WIDTH};
+use crablike::object::{DeathCallback, Item, Object};
+
+/// A fresh game with everything but the player cleared off the level.
+fn empty_level() -> (Game, Vec<Object>) {
+    let (game, mut objects) = new_game(7);
+    objects.truncate(PLAYER + 1);
+    (game, objects)
+}
+
+fn potion_under_player(objects: &mut Vec<Object>) {
+    let (x, y) = objects[PLAYER].pos();
+    let mut potion = Object::new(x, y, '!', "healing potion", VIOLET, false);
+    potion.item = Some(Item::Heal);
+    objects.push(potion);
+}
+
+#[test]
+fn identical_items_share_one_stack() {
+    let (mut game, mut objects) = empty_level();
+    for _ in 0..3 {
+        potion_under_player(&mut objects);
+        game.apply(Command::PickUp, &mut objects);
+    }
+
+    assert_eq!(game.inventory.len(), 1);
+    assert_eq!(game.inventory[0].stack_name(), "3 healing potions");
+}
+
+#[test]
+fn using_and_dropping_take_one_from_the_stack() {
+    let (mut game, mut objects) = empty_level();
+    for _ in 0..3 {
+        potion_under_player(&mut objects);
+        game.apply(Command::PickUp, &mut objects);
+    }
+
+    if let Some(fighter) = objects[PLAYER].fighter.as_mut() {
+        fighter.hp -= 10;
+    }
+    game.apply(Command::UseItem(0, None), &mut objects);
+    assert_eq!(game.inventory[0].count, 2);
+
+    game.apply(Command::Drop(0), &mut objects);
+    assert_eq!(game.inventory[0].count, 1);
+    let dropped = objects
+        .iter()
+        .filter(|o| o.pos() == objects[PLAYER].pos() && o.name == "healing potion")
+        .collect::<Vec<_>>();
+    assert_eq!(dropped.len(), 1);
+    assert_eq!(dropped[0].count, 1);
+
+    game.apply(Command::Drop(0), &mut objects);
+    assert!(game.inventory.is_empty());
+}
+
+#[test]
+fn full_inventory_still_takes_items_that_stack() {
+    let (mut game, mut objects) = empty_level();
+    potion_under_player(&mut objects);
+    game.apply(Command::PickUp, &mut objects);
+    for i in 0..25 {
+        let mut scroll = Object::new(0, 0, '#', &format!("scroll {}", i), VIOLET, false);
+        scroll.item = Some(Item::Lightning);
+        game.inventory.push(scroll);
+    }
+
+    potion_under_player(&mut objects);
+    game.apply(Command::PickUp, &mut objects);
+
+    assert_eq!(game.inventory.len(), 26);
+    assert_eq!(game.inventory[0].count, 2);
+}
+
+#[test]
+fn equipment_counts_for_the_player_whatever_their_name() {
+    let (mut game, mut objects) = empty_level();
+    let (x, y) = objects[PLAYER].pos();
+    let sword = game.templates.item("sword").unwrap().spawn(x, y);
+    objects.push(sword);
+    game.apply(Command::PickUp, &mut objects);
+    game.apply(Command::Equip(0), &mut objects);
+
+    objects[PLAYER].name = "Ferris".into();
+    assert_eq!(objects[PLAYER].power(&game), 8);
+
+    let mut impostor = Object::new(x, y, 'o', "player", VIOLET, true);
+    impostor.fighter = objects[PLAYER].fighter;
+    impostor.fighter.as_mut().unwrap().on_death = DeathCallback::Monster;
+    assert_eq!(impostor.power(&game), 5);
+}
+
+#[test]
+fn fireballs_do_not_burn_through_walls() {
+    let (mut game, mut objects) = empty_level();
+    game.map = vec![vec![Tile::empty(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+    for x in 5..25 {
+        game.map[x][12] = Tile::wall();
+    }
+    objects[PLAYER].set_pos(10, 10);
+    let orc = game.templates.monster("orc").unwrap();
+    objects.push(orc.spawn(14, 10));
+    objects.push(orc.spawn(14, 13));
+    game.compute_fov(&objects);
+
+    let mut scroll = Object::new(0, 0, '#', "scroll of fireball", VIOLET, false);
+    scroll.item = Some(Item::Fireball);
+    game.inventory.push(scroll);
+    game.apply(Command::UseItem(0, Some((14, 10))), &mut objects);
+
+    assert!(!objects[1].alive);
+    assert!(objects[2].alive);
+    assert_eq!(objects[2].fighter.map(|f| f.hp), Some(10));
+
+    let messages: Vec<_> = game
+        .messages
+        .iter()
+        .map(|(text, _)| text.as_str())
+        .collect();
+    assert!(messages.contains(&"The orc is burned to death (25 damage)."));
+    assert!(!messages.iter().any(|text| text.contains("died")));
+}
@@ -0,0 +1,81 @@
+use crablike::keybindings::*;
+
+fn pressed(spec: &str) -> KeySpec {
+    KeySpec::parse(spec).unwrap()
+}
+
+fn error_for(text: &str) -> String {
+    KeyBindings::parse(text).err().unwrap().to_string()
+}
+
+#[test]
+fn specs_parse_names_text_and_modifiers() {
+    assert_eq!(
+        KeySpec::parse("Ctrl+Alt+x"),
+        Some(KeySpec {
+            key: KeyName::Text("x".into()),
+            alt: true,
+            ctrl: true,
+        })
+    );
+    assert_eq!(
+        KeySpec::parse("numpad8").map(|spec| spec.key),
+        Some(KeyName::Named(NamedKey::NumPad8))
+    );
+    assert_ne!(pressed("c"), pressed("C"));
+    assert_eq!(pressed("Alt+enter").to_string(), "Alt+Enter");
+    assert_eq!(pressed("Alt+Ctrl+Space").to_string(), "Ctrl+Alt+Space");
+
+    assert_eq!(KeySpec::parse("Hyper+k"), None);
+    assert_eq!(KeySpec::parse("F13"), None);
+    assert_eq!(KeySpec::parse(""), None);
+}
+
+#[test]
+fn defaults_fill_in_actions_the_file_leaves_out() {
+    let bindings = KeyBindings::parse("pick_up = [\",\", \"Ctrl+g\"]\n").unwrap();
+
+    assert_eq!(bindings.action_for(&pressed(",")), Some(Action::PickUp));
+    assert_eq!(
+        bindings.action_for(&pressed("Ctrl+g")),
+        Some(Action::PickUp)
+    );
+    assert_eq!(bindings.action_for(&pressed("g")), None);
+    assert_eq!(bindings.action_for(&pressed("k")), Some(Action::MoveNorth));
+    assert_eq!(bindings.action_for(&pressed("Escape")), Some(Action::Quit));
+}
+
+#[test]
+fn modifier_bindings_win_over_the_bare_key() {
+    let bindings = KeyBindings::parse("").unwrap();
+
+    assert_eq!(
+        bindings.action_for(&pressed("Alt+Enter")),
+        Some(Action::ToggleFullscreen)
+    );
+    assert_eq!(bindings.action_for(&pressed("Enter")), None);
+    // Modifiers nothing is bound to are ignored.
+    assert_eq!(
+        bindings.action_for(&pressed("Ctrl+j")),
+        Some(Action::MoveSouth)
+    );
+}
+
+#[test]
+fn bad_files_are_rejected() {
+    assert_eq!(
+        error_for("pick_up = [\"Hyper+g\"]\n"),
+        "keybindings.toml: unknown key 'Hyper+g' for action 'pick_up'"
+    );
+    assert!(error_for("jump = [\"J\"]\n")
+        .starts_with("keybindings.toml: unknown action 'jump', expected one of: move_north,"));
+    assert_eq!(
+        error_for("drop = [\"g\"]\n"),
+        "keybindings.toml: key 'g' is bound to both 'pick_up' and 'drop'"
+    );
+    assert_eq!(
+        error_for("wait = [\"k\"]\n"),
+        "keybindings.toml: key 'k' is bound to both 'move_north' and 'wait'"
+    );
+    assert!(error_for("wait = \"k\"\n").starts_with("keybindings.toml: "));
+}
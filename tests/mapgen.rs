@@ -0,0 +1,142 @@
+use crablike::colors::WHITE;
+use crablike::gamemap::{make_map, Tile, MAP_HEIGHT, MAP_WIDTH};
+use crablike::mapgen::*;
+use crablike::object::Object;
+use crablike::spawn::Steps;
+use crablike::templates::Templates;
+use rand::SeedableRng;
+use rand_pcg::Pcg32;
+use std::collections::HashSet;
+
+fn layout(generator: Generator, seed: u64) -> Layout {
+    generator.generate(&mut Pcg32::seed_from_u64(seed))
+}
+
+/// Regions must be floor, must not share tiles and must have their center
+/// among their tiles.
+fn check_regions(layout: &Layout, seed: u64) {
+    let mut used = HashSet::new();
+    for region in &layout.regions {
+        assert!(region.tiles.contains(&region.center), "seed {}", seed);
+        for &(x, y) in &region.tiles {
+            assert!(!layout.map[x as usize][y as usize].blocked, "seed {}", seed);
+            assert!(
+                used.insert((x, y)),
+                "seed {}: ({}, {}) is shared",
+                seed,
+                x,
+                y
+            );
+        }
+    }
+}
+
+#[test]
+fn bsp_rooms_fill_the_map_without_overlapping() {
+    for seed in 0..200 {
+        let layout = layout(Generator::Bsp, seed);
+        assert!(layout.regions.len() >= 6, "seed {}", seed);
+        check_regions(&layout, seed);
+    }
+}
+
+#[test]
+fn caves_are_one_connected_area() {
+    for seed in 0..100 {
+        let layout = layout(Generator::Cave, seed);
+        assert!(layout.regions.len() >= 2, "seed {}", seed);
+        check_regions(&layout, seed);
+
+        let start = layout.regions[0].center;
+        let reachable: HashSet<_> = flood_fill(&layout.map, start).into_iter().collect();
+        let floor = layout
+            .map
+            .iter()
+            .flatten()
+            .filter(|tile| !tile.blocked)
+            .count();
+        assert_eq!(reachable.len(), floor, "seed {}", seed);
+    }
+}
+
+#[test]
+fn levels_table_picks_generators_by_depth() {
+    let templates = Templates::parse(
+        "test.toml",
+        "[levels]\ngenerator = [[1, \"rooms\"], [4, \"bsp\"], [6, \"cave\"]]\n",
+    )
+    .unwrap();
+    assert_eq!(templates.generator.at(3), Generator::Rooms);
+    assert_eq!(templates.generator.at(4), Generator::Bsp);
+    assert_eq!(templates.generator.at(9), Generator::Cave);
+
+    let default = Templates::parse("test.toml", "").unwrap();
+    assert_eq!(default.generator, Steps::default());
+    assert_eq!(default.generator.at(9), Generator::Rooms);
+
+    let error = Templates::parse("test.toml", "[levels]\ngenerator = \"maze\"\n")
+        .unwrap_err()
+        .to_string();
+    assert_eq!(
+        error,
+        "test.toml:2: levels field `generator`: expected \"rooms\" or \"bsp\" or \"cave\" or \
+         [[level, value], ...], found \"maze\""
+    );
+}
+
+#[test]
+fn farthest_floor_walks_around_walls() {
+    let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+    // A U-shaped corridor: down column 10, across row 20, back up column 14.
+    let corridor = (10..=20)
+        .flat_map(|y| vec![(10, y), (14, y)])
+        .chain((10..=14).map(|x| (x, 20)));
+    for (x, y) in corridor {
+        map[x][y] = Tile::empty();
+    }
+    map[14][10] = Tile::door();
+
+    assert_eq!(farthest_floor(&map, (10, 10)), (14, 11));
+    assert_eq!(farthest_floor(&map, (14, 20)), (10, 10));
+
+    let mut closet = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+    closet[5][5] = Tile::empty();
+    assert_eq!(farthest_floor(&closet, (5, 5)), (5, 5));
+}
+
+/// Monsters and items `make_map` spawns on `level` over a fixed run of
+/// seeds, with every level laid out by `generator` and no vaults.
+fn spawns(generator: Generator, level: u32) -> usize {
+    let mut templates = Templates::builtin();
+    templates.generator = Steps::constant(generator);
+    templates.vaults.clear();
+
+    (0..20)
+        .map(|seed| {
+            let mut objects = vec![Object::new(0, 0, '@', "player", WHITE, true)];
+            make_map(
+                &mut objects,
+                level,
+                &templates,
+                &mut Pcg32::seed_from_u64(seed),
+            );
+            // Leave out the player and the stairs.
+            objects.len() - 2
+        })
+        .sum()
+}
+
+#[test]
+fn caves_spawn_about_as_much_as_room_levels() {
+    for &level in &[1, 5] {
+        let rooms = spawns(Generator::Rooms, level) as f32;
+        let cave = spawns(Generator::Cave, level) as f32;
+        assert!(
+            (cave / rooms - 1.0).abs() < 0.3,
+            "level {}: {} spawns in caves against {} in rooms",
+            level,
+            cave,
+            rooms
+        );
+    }
+}
@@ -0,0 +1,36 @@
+use crablike::colors::{RED, WHITE};
+use crablike::messages::Messages;
+
+fn texts(messages: &Messages) -> Vec<&str> {
+    messages.iter().map(|(msg, _)| msg.as_str()).collect()
+}
+
+#[test]
+fn repeated_messages_collapse_into_a_count() {
+    let mut messages = Messages::new();
+    messages.add("The orc attacks you.", RED);
+    messages.add("The orc attacks you.", RED);
+    messages.add("The orc attacks you.", RED);
+    messages.add("You hit the orc.", WHITE);
+    messages.add("The orc attacks you.", RED);
+
+    assert_eq!(
+        texts(&messages),
+        vec![
+            "The orc attacks you. (x3)",
+            "You hit the orc.",
+            "The orc attacks you.",
+        ]
+    );
+}
+
+#[test]
+fn history_keeps_only_the_newest_messages() {
+    let mut messages = Messages::new();
+    messages.set_limit(3);
+    for i in 0..5 {
+        messages.add(format!("message {}", i), WHITE);
+    }
+
+    assert_eq!(texts(&messages), vec!["message 2", "message 3", "message 4"]);
+}
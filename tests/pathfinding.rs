@@ -0,0 +1,41 @@
+use crablike::colors::WHITE;
+use crablike::gamemap::{Map, Tile};
+use crablike::object::Object;
+use crablike::pathfinding::find_path;
+
+/// A 10x5 room with a wall across the middle that is open only at the
+/// bottom row.
+fn walled_room() -> Map {
+    let mut map = vec![vec![Tile::empty(); 5]; 10];
+    for tile in map[5].iter_mut().take(4) {
+        *tile = Tile::wall();
+    }
+    map
+}
+
+#[test]
+fn routes_around_walls() {
+    let map = walled_room();
+    let path = find_path((2, 1), (8, 1), &map, &[]).expect("target is reachable");
+
+    assert_eq!(path.last(), Some(&(8, 1)));
+    assert!(path.contains(&(5, 4)));
+    assert!(path.iter().all(|&(x, y)| !map[x as usize][y as usize].blocked));
+}
+
+#[test]
+fn walks_through_blocking_objects_when_there_is_no_detour() {
+    let map = walled_room();
+    let blocker = Object::new(5, 4, 'o', "orc", WHITE, true);
+    let path = find_path((2, 1), (8, 1), &map, &[blocker]);
+
+    assert!(path.expect("blocked gap still has a path").contains(&(5, 4)));
+}
+
+#[test]
+fn reports_unreachable_targets() {
+    let mut map = walled_room();
+    map[5][4] = Tile::wall();
+
+    assert_eq!(find_path((2, 1), (8, 1), &map, &[]), None);
+}
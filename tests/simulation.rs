@@ -0,0 +1,117 @@
+use crablike::game::*;
+use crablike::object::{Ai, DeathCallback, Fighter, Object};
+
+fn snapshot(objects: &[Object]) -> Vec<(String, i32, i32)> {
+    objects
+        .iter()
+        .map(|o| (o.name.clone(), o.x, o.y))
+        .collect()
+}
+
+fn play(seed: u64, commands: &[Command]) -> (Vec<Event>, Vec<(String, i32, i32)>) {
+    let (mut game, mut objects) = new_game(seed);
+    let mut events = vec![];
+    for &command in commands {
+        events.extend(game.apply(command, &mut objects));
+    }
+    (events, snapshot(&objects))
+}
+
+#[test]
+fn same_seed_generates_same_dungeon() {
+    let (game_a, objects_a) = new_game(42);
+    let (game_b, objects_b) = new_game(42);
+
+    let walls = |map: &crablike::gamemap::Map| -> Vec<Vec<bool>> {
+        map.iter()
+            .map(|column| column.iter().map(|tile| tile.blocked).collect())
+            .collect()
+    };
+
+    assert_eq!(walls(&game_a.map), walls(&game_b.map));
+    assert_eq!(snapshot(&objects_a), snapshot(&objects_b));
+}
+
+#[test]
+fn same_seed_and_commands_replay_identically() {
+    let commands: Vec<Command> = (0..200)
+        .map(|i| match i % 5 {
+            0 => Command::Move { dx: 1, dy: 0 },
+            1 => Command::Move { dx: 0, dy: 1 },
+            2 => Command::Move { dx: -1, dy: 0 },
+            3 => Command::PickUp,
+            _ => Command::Move { dx: 0, dy: -1 },
+        })
+        .collect();
+
+    let first = play(7, &commands);
+    let second = play(7, &commands);
+
+    assert!(!first.0.is_empty());
+    assert_eq!(first, second);
+}
+
+/// A fresh level holding only the player and an orc right next to them.
+fn next_to_orc(speed: i32) -> (Game, Vec<Object>) {
+    let (game, mut objects) = new_game(7);
+    objects.truncate(PLAYER + 1);
+
+    let (x, y) = objects[PLAYER].pos();
+    let mut orc = Object::new(x + 1, y, 'o', "orc", crablike::colors::WHITE, true);
+    orc.alive = true;
+    orc.fighter = Some(Fighter {
+        max_hp: 10,
+        hp: 10,
+        defense: 0,
+        power: 3,
+        xp: 35,
+        speed,
+        on_death: DeathCallback::Monster,
+    });
+    orc.ai = Some(Ai::Basic);
+    objects.push(orc);
+
+    (game, objects)
+}
+
+#[test]
+fn stats_count_turns_and_kills() {
+    let (mut game, mut objects) = next_to_orc(NORMAL_SPEED);
+
+    game.apply(Command::Move { dx: 1, dy: 0 }, &mut objects);
+    game.apply(Command::Move { dx: 1, dy: 0 }, &mut objects);
+    game.apply(Command::Wait, &mut objects);
+
+    assert_eq!(game.stats.turns, 3);
+    assert_eq!(game.stats.kills.get("orc"), Some(&1));
+    assert_eq!(game.stats.cause_of_death, None);
+}
+
+#[test]
+fn status_effects_show_an_unspent_level_up() {
+    let (game, mut objects) = new_game(1);
+    assert!(game.status_effects(&objects).is_empty());
+
+    let xp = game.xp_to_level_up(&objects);
+    objects[PLAYER].fighter.as_mut().unwrap().xp = xp;
+    assert_eq!(game.status_effects(&objects), vec!["ready to level up"]);
+
+    objects[PLAYER].alive = false;
+    assert!(game.status_effects(&objects).is_empty());
+}
+
+#[test]
+fn faster_monsters_act_more_often() {
+    let orc_attacks = |speed| {
+        let (mut game, mut objects) = next_to_orc(speed);
+        let events = game.apply(Command::Wait, &mut objects);
+        events
+            .iter()
+            .filter(|e| matches!(e, Event::Attacked { attacker, .. } if attacker == "orc"))
+            .count()
+    };
+
+    assert_eq!(orc_attacks(NORMAL_SPEED), 1);
+    assert_eq!(orc_attacks(NORMAL_SPEED * 2), 2);
+    assert_eq!(orc_attacks(NORMAL_SPEED / 2), 0);
+}
@@ -0,0 +1,131 @@
+use crablike::spawn::*;
+use crablike::templates::*;
+
+const GOBLIN: &str = r#"
+[[monster]]
+name = "goblin"
+glyph = "g"
+color = [0, 200, 0]
+hp = 6
+defense = 0
+power = 2
+xp = 20
+speed = 15
+ai = "basic"
+weight = 30
+min_depth = 2
+"#;
+
+fn error_for(text: &str) -> String {
+    Templates::parse("test.toml", text).unwrap_err().to_string()
+}
+
+#[test]
+fn builtin_templates_parse() {
+    let templates = Templates::builtin();
+    assert!(templates.monster("orc").is_some());
+    assert!(templates.monster("troll").is_some());
+    assert!(templates.item("healing potion").is_some());
+    assert!(templates.item("sword").and_then(|s| s.equipment).is_some());
+}
+
+#[test]
+fn new_monsters_need_only_data() {
+    let templates = Templates::parse("test.toml", GOBLIN).unwrap();
+    let goblin = templates.monster("goblin").unwrap();
+
+    assert_eq!(goblin.glyph, 'g');
+    assert_eq!(goblin.speed, 15);
+    assert_eq!(goblin.min_depth, 2);
+    assert_eq!(goblin.weight, Steps::constant(30));
+
+    let object = goblin.spawn(3, 4);
+    assert_eq!(object.pos(), (3, 4));
+    assert_eq!(object.fighter.map(|f| f.hp), Some(6));
+}
+
+#[test]
+fn errors_name_the_file_line_and_field() {
+    let bad_hp = GOBLIN.replace("hp = 6", "hp = \"six\"");
+    assert_eq!(
+        error_for(&bad_hp),
+        "test.toml:6: monster 'goblin' field `hp`: expected an integer, found string"
+    );
+
+    let bad_color = GOBLIN.replace("[0, 200, 0]", "\"mauve\"");
+    assert_eq!(
+        error_for(&bad_color),
+        "test.toml:5: monster 'goblin' field `color`: unknown color 'mauve'"
+    );
+
+    let missing_ai = GOBLIN.replace("ai = \"basic\"\n", "");
+    assert_eq!(
+        error_for(&missing_ai),
+        "test.toml:2: monster 'goblin' field `ai`: missing field"
+    );
+
+    let typo = GOBLIN.replace("power = 2", "powr = 2");
+    assert!(
+        error_for(&typo).starts_with("test.toml:8: monster 'goblin' field `powr`: unknown field")
+    );
+}
+
+#[test]
+fn errors_find_headers_with_comments_and_spaces() {
+    let text = GOBLIN.replace("[[monster]]", "[[monster]] # goblins")
+        + &GOBLIN
+            .replace("[[monster]]", "[[ monster ]]\n# no ai yet")
+            .replace("ai = \"basic\"\n", "");
+    assert_eq!(
+        error_for(&text),
+        "test.toml:15: monster 'goblin' field `ai`: missing field"
+    );
+}
+
+#[test]
+fn spawn_odds_step_with_depth() {
+    let text = GOBLIN.replace("weight = 30", "weight = [[2, 30], [5, 10]]")
+        + "\n[spawn]\nmax_monsters = [[1, 2], [4, 5]]\n";
+    let templates = Templates::parse("test.toml", &text).unwrap();
+    let goblin = templates.monster("goblin").unwrap();
+
+    assert_eq!(
+        (
+            goblin.weight.at(1),
+            goblin.weight.at(4),
+            goblin.weight.at(9)
+        ),
+        (0, 30, 10)
+    );
+    assert_eq!(templates.room_limits.max_monsters.at(3), 2);
+    assert_eq!(templates.room_limits.max_monsters.at(4), 5);
+    assert_eq!(templates.room_limits.max_items.at(4), 2);
+
+    assert!(templates.monster_table(1).distribution().is_empty());
+    assert_eq!(templates.monster_table(2).distribution().len(), 1);
+
+    let unsorted = GOBLIN.replace("weight = 30", "weight = [[3, 30], [2, 10]]");
+    assert_eq!(
+        error_for(&unsorted),
+        "test.toml:12: monster 'goblin' field `weight`: steps must be listed by rising level"
+    );
+}
+
+#[test]
+fn builtin_spawn_odds_favor_trolls_deeper_down() {
+    let templates = Templates::builtin();
+    let troll_chance = |level| {
+        templates
+            .monster_table(level)
+            .distribution()
+            .into_iter()
+            .find(|(monster, _)| monster.name == "troll")
+            .map_or(0.0, |(_, chance)| chance)
+    };
+
+    assert!(troll_chance(1) < troll_chance(3));
+    assert!(troll_chance(3) < troll_chance(7));
+    assert!(describe_spawns(&templates, 3)
+        .iter()
+        .any(|line| line.ends_with("% troll")));
+}
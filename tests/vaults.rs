@@ -0,0 +1,141 @@
+use crablike::gamemap::{Tile, MAP_HEIGHT, MAP_WIDTH};
+use crablike::mapgen::{flood_fill, Generator, Layout};
+use crablike::object::Object;
+use crablike::templates::Templates;
+use crablike::vault::*;
+use rand::SeedableRng;
+use rand_pcg::Pcg32;
+use std::collections::HashSet;
+
+const SHRINE: &str = r#"
+[[item]]
+name = "healing potion"
+glyph = "!"
+color = "violet"
+effect = "heal"
+weight = 1
+
+[[vault]]
+name = "shrine"
+rarity = 2
+min_depth = 3
+max_depth = 4
+plan = """
+#####
+#.!.#
+##.##
+"""
+legend = { "!" = "healing potion" }
+"#;
+
+fn error_for(text: &str) -> String {
+    Templates::parse("test.toml", text).unwrap_err().to_string()
+}
+
+#[test]
+fn vault_plans_are_read_with_their_markers() {
+    let templates = Templates::parse("test.toml", SHRINE).unwrap();
+    let shrine = &templates.vaults[0];
+
+    assert_eq!((shrine.width(), shrine.height()), (5, 3));
+    assert_eq!(shrine.plan[1][2], VaultCell::Item("healing potion".into()));
+    assert_eq!(shrine.entrances(), vec![(2, 2)]);
+    assert!(!shrine.allowed_on(2) && shrine.allowed_on(3) && !shrine.allowed_on(5));
+
+    assert!(!Templates::builtin().vaults.is_empty());
+}
+
+#[test]
+fn broken_plans_are_rejected() {
+    assert_eq!(
+        error_for(&SHRINE.replace("#.!.#", "#.?.#")),
+        "test.toml:14: vault 'shrine' field `plan`: unknown marker '?', add it to the legend"
+    );
+    assert_eq!(
+        error_for(&SHRINE.replace("##.##", "#####")),
+        "test.toml:14: vault 'shrine' field `plan`: the plan has no gap in its outer wall to \
+         enter by"
+    );
+    assert_eq!(
+        error_for(&SHRINE.replace("#.!.#", "#.#.#").replace("##.##", "#.###")),
+        "test.toml:14: vault 'shrine' field `plan`: floor at column 4, row 2 of the plan cannot \
+         be reached"
+    );
+    assert_eq!(
+        error_for(&SHRINE.replace("\"healing potion\" }", "\"dragon\" }")),
+        "test.toml:19: vault 'shrine' field `legend`: 'dragon' is not a monster or item in this \
+         file"
+    );
+}
+
+#[test]
+fn stamped_vaults_are_reachable_from_the_start() {
+    let templates = Templates::parse("test.toml", SHRINE).unwrap();
+    let shrine = &templates.vaults[0];
+
+    for seed in 0..20 {
+        let Layout { mut map, regions } =
+            Generator::Rooms.generate(&mut Pcg32::seed_from_u64(seed));
+        let start = regions[0].center;
+        let mut objects: Vec<Object> = vec![];
+
+        // Any spot on top of existing floor must be refused untouched.
+        let before = map.clone();
+        let (rx, ry) = regions[0].center;
+        assert!(!place_vault(
+            shrine,
+            rx - 2,
+            ry - 1,
+            &mut map,
+            start,
+            &mut objects,
+            &templates
+        ));
+        assert!(objects.is_empty());
+        assert_eq!(format!("{:?}", map), format!("{:?}", before));
+
+        let placed = (1..MAP_WIDTH - shrine.width())
+            .flat_map(|x| (1..MAP_HEIGHT - shrine.height()).map(move |y| (x, y)))
+            .find(|&(x, y)| place_vault(shrine, x, y, &mut map, start, &mut objects, &templates));
+        let (x, y) = placed.expect("the rooms generator leaves plenty of rock");
+
+        let reachable: HashSet<_> = flood_fill(&map, start).into_iter().collect();
+        for &(fx, fy) in &[(1, 1), (2, 1), (3, 1), (2, 2)] {
+            assert!(reachable.contains(&(x + fx, y + fy)), "seed {}", seed);
+        }
+        assert_eq!(objects.len(), 1);
+        assert_eq!(objects[0].pos(), (x + 2, y + 1));
+    }
+}
+
+#[test]
+fn tunnels_into_rooms_end_in_a_door() {
+    let templates = Templates::parse("test.toml", SHRINE).unwrap();
+    let shrine = &templates.vaults[0];
+
+    // A room under the vault spot with its own door two tiles along from
+    // where the vault's tunnel comes down.
+    let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+    for column in &mut map[20..=30] {
+        for tile in &mut column[20..=25] {
+            *tile = Tile::empty();
+        }
+    }
+    map[26][19] = Tile::door();
+    let mut objects = vec![];
+
+    assert!(place_vault(
+        shrine,
+        22,
+        14,
+        &mut map,
+        (25, 22),
+        &mut objects,
+        &templates
+    ));
+
+    assert!(map[24][18].is_walkable() && !map[24][18].door);
+    assert!(map[24][19].is_closed_door());
+    let gaps: Vec<_> = (19..=31).filter(|&x| !map[x][19].blocked).collect();
+    assert!(gaps.is_empty(), "open gaps in the room wall at {:?}", gaps);
+}